@@ -19,10 +19,9 @@ pub fn get_initial_planet_map(
         fractal_options,
         options.noise_mask_options.mask_frequency,
         options.noise_mask_options.mask_z,
-        options.global_noise_options.amplitude,
+        &options.global_noise_options,
         options.displacement_scale,
         options.displacement_frequency,
-        options.global_noise_options.frequency,
     )?;
 
     // let surface_distance_field = get_surface_distance_field(&map, &get_surface(&map));
@@ -399,9 +398,12 @@ pub fn average_center(tiles: &[Coord]) -> Coord {
 pub fn edge_average_center(tiles: &[Coord], edges: &[usize]) -> Coord {
 
     let z: Vec<Coord> = edges.iter().map(|&i| tiles[i]).collect();
+    if z.is_empty() {
+        return average_center(tiles);
+    }
 
     let coord_average = z.iter().fold((0, 0), |acc, coord| (acc.0 + coord.x, acc.1 + coord.y));
-    let count = tiles.len();
+    let count = z.len();
     Coord {
         x: coord_average.0 / count,
         y: coord_average.1 / count,
@@ -427,6 +429,55 @@ pub fn dist(a: &Coord, b: &Coord) -> f32 {
     ((dx * dx + dy * dy) as f32).sqrt()
 }
 
+/// Chamfer distance from every set cell to the nearest unset cell, indexed
+/// [x][y]. Cells beyond the border count as unset, unset cells are 0.
+pub fn distance_transform(mask: &[Vec<bool>]) -> FMap {
+    let w = mask.len();
+    let h = mask.first().map_or(0, |column| column.len());
+    let mut out: FMap = mask
+        .iter()
+        .map(|column| column.iter().map(|&set| if set { f32::INFINITY } else { 0. }).collect())
+        .collect();
+
+    let get = |out: &FMap, x: isize, y: isize| {
+        if x < 0 || y < 0 || x >= w as isize || y >= h as isize {
+            0.
+        } else {
+            out[x as usize][y as usize]
+        }
+    };
+    let diagonal = std::f32::consts::SQRT_2;
+    let forward = [(-1, -1, diagonal), (-1, 0, 1.), (-1, 1, diagonal), (0, -1, 1.)];
+
+    for x in 0..w {
+        for y in 0..h {
+            if out[x][y] == 0. {
+                continue;
+            }
+            let (xi, yi) = (x as isize, y as isize);
+            out[x][y] = forward
+                .iter()
+                .map(|&(dx, dy, cost)| get(&out, xi + dx, yi + dy) + cost)
+                .fold(out[x][y], f32::min);
+        }
+    }
+
+    for x in (0..w).rev() {
+        for y in (0..h).rev() {
+            if out[x][y] == 0. {
+                continue;
+            }
+            let (xi, yi) = (x as isize, y as isize);
+            out[x][y] = forward
+                .iter()
+                .map(|&(dx, dy, cost)| get(&out, xi - dx, yi - dy) + cost)
+                .fold(out[x][y], f32::min);
+        }
+    }
+
+    out
+}
+
 fn get_surface_distance_field(map: &UMap8, surface: &Vec<Coord>) -> FMap {
 
     let mut out = FMap::blank(map.len());
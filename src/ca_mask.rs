@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+
+use crate::{
+    bit_map::{image_buffer_to_fmap, rgba_image_blur, umap_to_image_buffer, MapOpps},
+    map_data::MapData,
+    types::{CaMaskOptions, FMap},
+};
+
+/// Builds the mask that weights the initial CA state.
+///
+/// Starts from the blurred and inverted planet, so values grow towards the
+/// surface and outside it, then applies `mult` and `lift`, the depth falloff
+/// and every mask image in order, and finally clamps the result.
+pub fn build_ca_mask(map_data: &MapData, options: &CaMaskOptions) -> Result<FMap> {
+    let blurred_edge = rgba_image_blur(&umap_to_image_buffer(&map_data.raw_map), options.blur);
+    let mut mask = image_buffer_to_fmap(&blurred_edge);
+
+    mask.clamp(0., 1.);
+    mask.invert();
+    mask.mult(options.mult);
+    mask.lift(options.lift);
+
+    if let Some(curve) = &options.depth_falloff {
+        for (column, depth) in mask.iter_mut().zip(&map_data.depth_field) {
+            for (value, d) in column.iter_mut().zip(depth) {
+                *value *= curve.sample(*d);
+            }
+        }
+    }
+
+    for image in &options.images {
+        let mut layer = load_mask_image(&image.path, mask.len())?;
+        if image.invert {
+            layer.invert();
+        }
+
+        for (column, layer) in mask.iter_mut().zip(&layer) {
+            for (value, l) in column.iter_mut().zip(layer) {
+                *value = image.blend.apply(*value, *l);
+            }
+        }
+    }
+
+    mask.clamp(options.clamp_min, options.clamp_max);
+
+    Ok(mask)
+}
+
+/// Loads an image as grayscale brightness in `0..=1`, resampled to `resolution` square
+pub fn load_mask_image(path: &Path, resolution: usize) -> Result<FMap> {
+    let image = image::open(path)
+        .with_context(|| format!("failed to open mask image {}", path.display()))?
+        .to_luma8();
+    let resized = imageops::resize(&image, resolution as u32, resolution as u32, FilterType::Triangle);
+
+    Ok((0..resolution as u32)
+        .map(|x| {
+            (0..resolution as u32)
+                .map(|y| resized.get_pixel(x, y).0[0] as f32 / 255.)
+                .collect()
+        })
+        .collect())
+}
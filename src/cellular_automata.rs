@@ -1,14 +1,23 @@
 
+use std::cmp::Ordering;
+
 use crate::{
     map_data::MapData,
-    types::{Blank, FMap, UMap8},
+    summed_area::SummedAreaTable,
+    types::{
+        Blank, CaCounting, CaField, CaNeighbourhood, CaOptions, CaRule, CaStep, FMap, UMap8,
+    },
     PlanetOptions,
 };
+use anyhow::{anyhow, Result};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use rayon::prelude::*;
 
-fn precompute_circle_offsets(radius: u32) -> Vec<(i32, i32)> {
+/// most bands either side of the center when approximating a disc, see `precompute_disc_bands`
+const MAX_DISC_BANDS: i32 = 4;
+
+pub fn precompute_circle_offsets(radius: u32) -> Vec<(i32, i32)> {
     let mut offsets = Vec::new();
 
     for dy in -(radius as i32)..=(radius as i32) {
@@ -23,7 +32,234 @@ fn precompute_circle_offsets(radius: u32) -> Vec<(i32, i32)> {
     offsets
 }
 
-pub fn simulate_ca(options: &PlanetOptions, initial_state: UMap8, map_data: &MapData) -> UMap8 {
+/// Offsets of the tiles counted as neighbours.
+///
+/// The circle includes the center tile, as it always has, the other shapes
+/// leave it out. Moore is always the 8 surrounding tiles.
+fn precompute_offsets(shape: CaNeighbourhood, radius: u32) -> Vec<(i32, i32)> {
+    let r = radius as i32;
+    let square = |r: i32| {
+        (-r..=r)
+            .flat_map(move |dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx != 0 || dy != 0)
+    };
+
+    match shape {
+        CaNeighbourhood::Circle => precompute_circle_offsets(radius),
+        CaNeighbourhood::Square => square(r).collect(),
+        CaNeighbourhood::VonNeumann => square(r)
+            .filter(|&(dx, dy)| dx.abs() + dy.abs() <= r)
+            .collect(),
+        CaNeighbourhood::Moore => square(1).collect(),
+    }
+}
+
+/// gaussian falloff over the offsets, for the weighted rule
+fn precompute_weights(offsets: &[(i32, i32)], sigma: f32) -> Vec<f32> {
+    let sigma = sigma.max(0.01);
+    offsets
+        .iter()
+        .map(|&(dx, dy)| (-((dx * dx + dy * dy) as f32) / (2. * sigma * sigma)).exp())
+        .collect()
+}
+
+/// Birth and survival counts of a Life-like rule string such as `B3/S23`.
+///
+/// Counts can be written as single digits, or separated by commas for
+/// neighbourhoods with more than 9 tiles, as in `B12,13/S10,11,12`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LifeRule {
+    pub birth: Vec<u32>,
+    pub survival: Vec<u32>,
+}
+
+impl LifeRule {
+    pub fn parse(rule: &str) -> Result<LifeRule> {
+        let mut out = LifeRule::default();
+
+        for part in rule.split('/') {
+            let part = part.trim();
+            let (counts, digits) = match part.chars().next() {
+                Some('B') | Some('b') => (&mut out.birth, &part[1..]),
+                Some('S') | Some('s') => (&mut out.survival, &part[1..]),
+                _ => return Err(anyhow!("invalid life rule {:?}, expected B../S..", rule)),
+            };
+
+            let parsed: Option<Vec<u32>> = if digits.contains(',') {
+                digits.split(',').map(|d| d.trim().parse().ok()).collect()
+            } else {
+                digits.chars().map(|c| c.to_digit(10)).collect()
+            };
+
+            *counts = parsed.ok_or_else(|| anyhow!("invalid counts in life rule {:?}", rule))?;
+        }
+
+        Ok(out)
+    }
+
+    /// Fails on a count above `max`, which a neighbourhood of `max` tiles can never reach
+    pub fn check_counts(&self, max: u32) -> Result<()> {
+        match self.birth.iter().chain(&self.survival).find(|&&c| c > max) {
+            Some(c) => Err(anyhow!(
+                "count {} in life rule is above the {} tiles of the neighbourhood",
+                c,
+                max
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the rule from the options, with any rule string parsed up front
+enum Rule {
+    Threshold,
+    LifeLike(LifeRule),
+    Majority,
+    Weighted { threshold: f32 },
+    BirthSurvival { birth: u32, survival: u32 },
+}
+
+impl Rule {
+    fn from_options(rule: &CaRule) -> Result<Rule> {
+        Ok(match rule {
+            CaRule::Threshold => Rule::Threshold,
+            CaRule::LifeLike(s) => Rule::LifeLike(LifeRule::parse(s)?),
+            CaRule::Majority => Rule::Majority,
+            CaRule::Weighted { threshold, .. } => Rule::Weighted {
+                threshold: *threshold,
+            },
+            CaRule::BirthSurvival { birth, survival } => Rule::BirthSurvival {
+                birth: *birth,
+                survival: *survival,
+            },
+        })
+    }
+}
+
+/// neighbour offsets, and kernel weights for the weighted rule, for every radius up to the max
+struct Neighbourhoods {
+    offsets: Vec<Vec<(i32, i32)>>,
+    weights: Vec<Vec<f32>>,
+    /// disc approximations for summed area counting, see `precompute_disc_bands`
+    bands: Vec<Vec<(i32, i32, i32)>>,
+    /// number of tiles counted at each radius
+    sizes: Vec<u32>,
+    summed_area: bool,
+}
+
+impl Neighbourhoods {
+    fn new(ca_options: &CaOptions) -> Self {
+        let max_radius = max_search_radius(ca_options);
+        let offsets: Vec<Vec<(i32, i32)>> = (0..=max_radius)
+            .map(|r| precompute_offsets(ca_options.neighbourhood, r))
+            .collect();
+        let weights = match ca_options.rule {
+            CaRule::Weighted { sigma, .. } => {
+                offsets.iter().map(|o| precompute_weights(o, sigma)).collect()
+            }
+            _ => vec![],
+        };
+
+        // summed area tables only cover rectangles, and the weighted rule needs every offset
+        let summed_area = ca_options.counting == CaCounting::SummedArea
+            && matches!(
+                ca_options.neighbourhood,
+                CaNeighbourhood::Circle | CaNeighbourhood::Square
+            )
+            && !matches!(ca_options.rule, CaRule::Weighted { .. });
+
+        let bands: Vec<Vec<(i32, i32, i32)>> =
+            if summed_area && ca_options.neighbourhood == CaNeighbourhood::Circle {
+                (0..=max_radius).map(precompute_disc_bands).collect()
+            } else {
+                vec![]
+            };
+
+        let sizes = if bands.is_empty() {
+            offsets.iter().map(|o| o.len() as u32).collect()
+        } else {
+            bands
+                .iter()
+                .map(|b| {
+                    b.iter()
+                        .map(|&(dy0, dy1, w)| ((dy1 - dy0 + 1) * (2 * w + 1)) as u32)
+                        .sum()
+                })
+                .collect()
+        };
+
+        Neighbourhoods {
+            offsets,
+            weights,
+            bands,
+            sizes,
+            summed_area,
+        }
+    }
+}
+
+/// everything needed to update tiles during one step of the schedule
+struct StepRules {
+    options: CaOptions,
+    rule: Rule,
+    neighbourhoods: Neighbourhoods,
+}
+
+impl StepRules {
+    fn new(options: CaOptions) -> Result<Self> {
+        let rule = Rule::from_options(&options.rule)?;
+        let neighbourhoods = Neighbourhoods::new(&options);
+        if let Rule::LifeLike(life) = &rule {
+            life.check_counts(neighbourhoods.sizes.iter().copied().max().unwrap_or(0))?;
+        }
+
+        Ok(StepRules {
+            options,
+            rule,
+            neighbourhoods,
+        })
+    }
+}
+
+/// Approximates a disc as horizontal bands of `(dy_min, dy_max, half_width)`.
+///
+/// Rows are grouped into at most `MAX_DISC_BANDS` runs either side of the
+/// center, each as wide as the mean width of its rows, so counting costs the
+/// same at any radius. Small radii get one band per row and are exact.
+fn precompute_disc_bands(radius: u32) -> Vec<(i32, i32, i32)> {
+    let r = radius as i32;
+    let half_width = |dy: i32| ((r * r - dy * dy) as f32).sqrt() as i32;
+    let groups = (r + 1).min(MAX_DISC_BANDS);
+    let mut bands = Vec::new();
+
+    for g in 0..groups {
+        let a = g * (r + 1) / groups;
+        let b = (g + 1) * (r + 1) / groups - 1;
+        let mean = (a..=b).map(half_width).sum::<i32>() as f32 / (b - a + 1) as f32;
+        let w = mean.round() as i32;
+
+        if a == 0 {
+            bands.push((-b, b, w));
+        } else {
+            bands.push((a, b, w));
+            bands.push((-b, -a, w));
+        }
+    }
+
+    bands
+}
+
+/// Runs every step of the CA schedule in order.
+///
+/// Without a schedule a single step is built from the top level options.
+/// Tiles outside a step's mask keep their state for that step. When
+/// `record_frames` is set the initial state and every iteration are returned
+/// alongside the result.
+pub fn simulate_ca(
+    options: &PlanetOptions,
+    initial_state: UMap8,
+    map_data: &MapData,
+) -> Result<(UMap8, Vec<UMap8>)> {
 
     // let instant: Instant = Instant::now();
     // these get mem swapped
@@ -31,49 +267,91 @@ pub fn simulate_ca(options: &PlanetOptions, initial_state: UMap8, map_data: &Map
     let mut map1 = initial_state;
     let mut map2 = UMap8::blank(options.resolution() as usize);
 
-    let iters = options.ca_options.iterations;
+    let field = select_field(options.ca_options.modulation.field, map_data);
+    let record = options.ca_options.record_frames;
+    let mut frames = Vec::new();
 
-    let iters = if iters % 2 != 0 {
-        iters + 1
-    } else {
-        iters
-    };
-
-    let offsets = precompute_circle_offsets(options.ca_options.search_radius);
+    if record {
+        frames.push(map1.clone());
+    }
 
-    for _i in 1..iters {
-        map2.par_iter_mut().enumerate().for_each(|(y, row)| {
-            for xx in 0..options.resolution() {
-                let x = xx as usize;
+    for step in schedule(&options.ca_options) {
+        let rules = StepRules::new(step_options(&options.ca_options, &step))?;
+        let mask = step.mask.as_ref().map(|m| (m, select_field(m.field, map_data)));
+
+        for _i in 0..step.iterations {
+            let table = rules
+                .neighbourhoods
+                .summed_area
+                .then(|| SummedAreaTable::new(&map1));
+
+            map2.par_iter_mut().enumerate().for_each(|(x, row)| {
+                for (y, tile) in row.iter_mut().enumerate() {
+                    if let Some((m, mask_field)) = mask {
+                        let v = mask_field[x][y];
+                        if v < m.min || v > m.max {
+                            *tile = map1[x][y];
+                            continue;
+                        }
+                    }
+
+                    *tile = next_state(&x, &y, &map1, field, &rules, table.as_ref());
+                }
+            });
 
-                let d = decision(&x, &(y as usize), &map1, &map_data.altitude_field, &options, &offsets);
+            std::mem::swap(&mut map1, &mut map2);
 
-                if d {
-                    row[x] = 0;
-                } else {
-                    row[x] = 1;
-                }
+            if record {
+                frames.push(map1.clone());
             }
-        });
-
-        std::mem::swap(&mut map1, &mut map2);
+        }
     }
 
     if options.ca_options.invert {
-        for y in 0..options.resolution() {
-            for x in 0..options.resolution() {
-                map1[y as usize][x as usize] = if map1[y as usize][x as usize] == 0 {
-                    1
-                } else {
-                    0
-                };
+        for map in std::iter::once(&mut map1).chain(frames.iter_mut()) {
+            for y in 0..options.resolution() {
+                for x in 0..options.resolution() {
+                    map[y as usize][x as usize] = if map[y as usize][x as usize] == 0 {
+                        1
+                    } else {
+                        0
+                    };
+                }
             }
         }
     }
 
     // println!("ca took {:?}", instant.elapsed());
 
-    map1
+    Ok((map1, frames))
+}
+
+fn schedule(ca_options: &CaOptions) -> Vec<CaStep> {
+    if !ca_options.schedule.is_empty() {
+        return ca_options.schedule.clone();
+    }
+
+    vec![CaStep {
+        iterations: ca_options.iterations,
+        search_radius: ca_options.search_radius,
+        threshold: ca_options.threshold,
+        neighbourhood: ca_options.neighbourhood,
+        rule: ca_options.rule.clone(),
+        mask: None,
+    }]
+}
+
+/// the top level options with the step's own values swapped in
+fn step_options(ca_options: &CaOptions, step: &CaStep) -> CaOptions {
+    CaOptions {
+        iterations: step.iterations,
+        search_radius: step.search_radius,
+        threshold: step.threshold,
+        neighbourhood: step.neighbourhood,
+        rule: step.rule.clone(),
+        schedule: vec![],
+        ..ca_options.clone()
+    }
 }
 
 // pub fn _simulate_ca(options: &PlanetOptions, _map: &UMap8, altitude: &FMap) -> UMap8 {
@@ -124,28 +402,132 @@ pub fn simulate_ca(options: &PlanetOptions, initial_state: UMap8, map_data: &Map
 //     map1
 // }
 
-fn decision(
+fn next_state(
     x: &usize,
     y: &usize,
     img: &Vec<Vec<u8>>,
-    _altitude: &FMap,
-    options: &PlanetOptions,
-    circle_offsets: &Vec<(i32, i32)>,
-) -> bool {
-    // let result = get_neighboring_wall_tile_count_diagonal(x, y, img);
-    // let result = get_neighboring_wall_tile_count_horizontal_and_vertical(x, y, img);
-    let result = get_neighboring_wall_tile_count_within_radius_circle(
-        x,
-        y,
-        img,
-        options.ca_options.search_radius,
-        circle_offsets,
-    );
-    // let result = get_neighboring_wall_tile_count_within_radius_circle(x, y, img, 3);
+    field: &FMap,
+    rules: &StepRules,
+    table: Option<&SummedAreaTable>,
+) -> u8 {
+    let ca_options = &rules.options;
+    let neighbourhoods = &rules.neighbourhoods;
+    let value = field[*x][*y];
+    let radius = search_radius_at(ca_options, value) as usize;
+    let offsets = &neighbourhoods.offsets[radius];
+    let size = neighbourhoods.sizes[radius];
+
+    let result = match table {
+        Some(table) => get_neighboring_wall_tile_count_summed_area(
+            x,
+            y,
+            img,
+            table,
+            radius as i32,
+            ca_options.neighbourhood,
+            neighbourhoods.bands.get(radius),
+        ),
+        None => get_neighboring_wall_tile_count_within_radius_circle(
+            x,
+            y,
+            img,
+            (radius as u32).max(1),
+            offsets,
+        ),
+    };
 
-    // let a: i32 = (altitude[*y][*x] as i32) * options.ca_misc;
-    let thresh: i32 = options.ca_options.threshold as i32;
-    result > thresh as u32
+    // keep thresholds proportional to the neighbourhood area when the radius is modulated
+    let area_ratio = size as f32 / neighbourhoods.sizes[ca_options.search_radius as usize] as f32;
+    let scale = threshold_scale(ca_options, value) * area_ratio;
+
+    let current = img[*x][*y];
+    // the circle counts the tile itself, the rules below only want its neighbours
+    let neighbours = if ca_options.neighbourhood == CaNeighbourhood::Circle {
+        result - current as u32
+    } else {
+        result
+    };
+
+    let alive = match &rules.rule {
+        Rule::Threshold => (result as f32) <= ca_options.threshold as f32 * scale,
+        Rule::LifeLike(life) => {
+            if current == 1 {
+                life.survival.contains(&neighbours)
+            } else {
+                life.birth.contains(&neighbours)
+            }
+        }
+        Rule::Majority => {
+            let total = if ca_options.neighbourhood == CaNeighbourhood::Circle {
+                size - 1
+            } else {
+                size
+            };
+            match (neighbours * 2).cmp(&total) {
+                Ordering::Greater => true,
+                Ordering::Less => false,
+                Ordering::Equal => current == 1,
+            }
+        }
+        Rule::Weighted { threshold } => {
+            let weights = &neighbourhoods.weights[radius];
+            get_weighted_neighbour_fraction(x, y, img, offsets, weights) > *threshold
+        }
+        Rule::BirthSurvival { birth, survival } => {
+            if current == 1 {
+                neighbours as f32 >= *survival as f32 * scale
+            } else {
+                neighbours as f32 >= *birth as f32 * scale
+            }
+        }
+    };
+
+    alive as u8
+}
+
+fn select_field(field: CaField, map_data: &MapData) -> &FMap {
+    match field {
+        CaField::Depth => &map_data.depth_field,
+        CaField::Altitude => &map_data.altitude_field,
+    }
+}
+
+fn max_search_radius(ca_options: &CaOptions) -> u32 {
+    match &ca_options.modulation.search_radius {
+        Some(curve) => {
+            ((ca_options.search_radius as f32 * curve.max()).round() as u32).max(ca_options.search_radius)
+        }
+        None => ca_options.search_radius,
+    }
+}
+
+fn search_radius_at(ca_options: &CaOptions, value: f32) -> u32 {
+    match &ca_options.modulation.search_radius {
+        Some(curve) => ((ca_options.search_radius as f32 * curve.sample(value)).round() as u32)
+            .max(1)
+            .min(max_search_radius(ca_options)),
+        None => ca_options.search_radius,
+    }
+}
+
+fn threshold_scale(ca_options: &CaOptions, value: f32) -> f32 {
+    match &ca_options.modulation.threshold {
+        Some(curve) => curve.sample(value),
+        None => 1.,
+    }
+}
+
+/// Scales the initial CA density per tile by the init weight curve, if one is set
+pub fn init_weight_scale(ca_options: &CaOptions, map_data: &MapData) -> Option<FMap> {
+    let curve = ca_options.modulation.init_weight.as_ref()?;
+    let field = select_field(ca_options.modulation.field, map_data);
+
+    Some(
+        field
+            .iter()
+            .map(|row| row.iter().map(|value| curve.sample(*value)).collect())
+            .collect(),
+    )
 }
 
 fn _decision(
@@ -231,89 +613,64 @@ fn get_neighboring_wall_tile_count_within_radius_circle(
 //     count
 // }
 
-fn get_neighboring_wall_tile_count_within_radius_square(
+/// Counts with rectangle sums, at the same cost for any radius.
+/// Squares leave out the tile itself, discs are approximated by bands.
+fn get_neighboring_wall_tile_count_summed_area(
     x: &usize,
     y: &usize,
-    img: &Vec<Vec<u8>>,
-    radius: u32,
+    img: &[Vec<u8>],
+    table: &SummedAreaTable,
+    radius: i32,
+    shape: CaNeighbourhood,
+    bands: Option<&Vec<(i32, i32, i32)>>,
 ) -> u32 {
-    let width = img.len() as i32;
-    let height = img[0].len() as i32;
-    let mut count: u32 = 0;
-
-    for dy in -(radius as i32)..=(radius as i32) {
-        for dx in -(radius as i32)..=(radius as i32) {
-            if dx == 0 && dy == 0 {
-                continue; // Skip the center pixel
-            }
-
-            let nx = *x as i32 + dx;
-            let ny = *y as i32 + dy;
-
-            if nx >= 0 && nx < width && ny >= 0 && ny < height {
-                let neighbor = img[nx as usize][ny as usize];
-                count += neighbor as u32;
-            }
+    let x = *x as i32;
+    let y = *y as i32;
+
+    match (shape, bands) {
+        (CaNeighbourhood::Circle, Some(bands)) => bands
+            .iter()
+            .map(|&(dy0, dy1, w)| table.rect_sum(x - w, x + w, y + dy0, y + dy1))
+            .sum(),
+        _ => {
+            table.rect_sum(x - radius, x + radius, y - radius, y + radius)
+                - img[x as usize][y as usize] as u32
         }
     }
-
-    count
 }
 
-fn get_neighboring_wall_tile_count_horizontal_and_vertical(
+/// weighted share of the neighbourhood that is set, from 0 to 1
+fn get_weighted_neighbour_fraction(
     x: &usize,
     y: &usize,
-    img: &Vec<Vec<u8>>,
-) -> u32 {
-    let width = img.len() as usize;
-    let height = img[0].len() as usize;
-    let mut count: u32 = 0;
-
-    if *x > 0 {
-        let neighbor = img[x - 1][*y];
-        count += neighbor as u32;
-    }
+    img: &[Vec<u8>],
+    offsets: &[(i32, i32)],
+    weights: &[f32],
+) -> f32 {
+    let width = img.len() as i32;
+    let height = img[0].len() as i32;
+    let mut sum = 0.;
+    let mut total = 0.;
 
-    if *x < width - 1 {
-        let neighbor = img[x + 1][*y];
-        count += neighbor as u32;
-    }
+    for (&(dx, dy), &w) in offsets.iter().zip(weights) {
+        if dx == 0 && dy == 0 {
+            continue;
+        }
 
-    if *y > 0 {
-        let neighbor = img[*x][y - 1];
-        count += neighbor as u32;
-    }
+        let nx = *x as i32 + dx;
+        let ny = *y as i32 + dy;
 
-    if *y < height - 1 {
-        let neighbor = img[*x][y + 1];
-        count += neighbor as u32;
+        if nx >= 0 && nx < width && ny >= 0 && ny < height {
+            sum += img[nx as usize][ny as usize] as f32 * w;
+            total += w;
+        }
     }
 
-    count
-}
-
-fn get_neighboring_wall_tile_count_diagonal(x: &usize, y: &usize, img: &Vec<Vec<u8>>) -> u32 {
-    let width = img.len() as u32;
-    let height = img[0].len() as u32;
-
-    let mut count: u32 = 0;
-
-    for dy in -1..=1 {
-        for dx in -1..=1 {
-            if dx == 0 && dy == 0 {
-                continue;
-            }
-
-            let nx = *x as i32 + dx;
-            let ny = *y as i32 + dy;
-
-            if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                let neighbor = img[nx as usize][ny as usize];
-                count += neighbor as u32;
-            }
-        }
+    if total > 0. {
+        sum / total
+    } else {
+        0.
     }
-    count
 }
 
 fn random_distribution(resolution: u32, weight: f32) -> Vec<Vec<u8>> {
@@ -329,3 +686,45 @@ fn random_distribution(resolution: u32, weight: f32) -> Vec<Vec<u8>> {
 
     img
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_life_rules() {
+        let rule = LifeRule::parse("B3/S23").unwrap();
+        assert_eq!(rule.birth, vec![3]);
+        assert_eq!(rule.survival, vec![2, 3]);
+
+        let rule = LifeRule::parse(" s12345 / b3 ").unwrap();
+        assert_eq!(rule.birth, vec![3]);
+        assert_eq!(rule.survival, vec![1, 2, 3, 4, 5]);
+
+        let rule = LifeRule::parse("B10,12/S9, 24").unwrap();
+        assert_eq!(rule.birth, vec![10, 12]);
+        assert_eq!(rule.survival, vec![9, 24]);
+    }
+
+    #[test]
+    fn rejects_malformed_life_rules() {
+        for rule in ["", "3/23", "B3/X23", "B3a/S23", "B3/S2,,3", "B-1/S2"] {
+            assert!(LifeRule::parse(rule).is_err(), "{:?} should not parse", rule);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_life_rules() {
+        assert!(LifeRule::parse("B3,99999999999/S2").is_err());
+
+        let options = |rule: &str| CaOptions {
+            search_radius: 1,
+            neighbourhood: CaNeighbourhood::Moore,
+            rule: CaRule::LifeLike(rule.to_string()),
+            ..Default::default()
+        };
+        assert!(StepRules::new(options("B3/S12345678")).is_ok());
+        assert!(StepRules::new(options("B9/S23")).is_err());
+        assert!(StepRules::new(options("B3/S2,30")).is_err());
+    }
+}
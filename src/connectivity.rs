@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+
+use crate::{
+    bit_map::simple_line,
+    designer_maps::CONSTRAINT_SOLID,
+    room::{closest_tiles, neighbour_coords, Room},
+    tile_map::{Tile, TileMap},
+    types::{Adjacency, ConnectivityReport, Coord, Entrance, UMap8},
+};
+
+/// tiles either side of the centre line of a repair tunnel
+const REPAIR_HALF_WIDTH: isize = 1;
+
+/// Makes every room reachable from the entrance through open tiles.
+///
+/// Flood fills the tile map from the entrance, then repeatedly carves a tunnel
+/// from the nearest reached room, or the entrance itself, to the closest room
+/// that was not reached, and continues the flood fill from the new tunnel.
+/// Repair tunnels respect forced solid constraints, so a room they cannot get
+/// to is reported as unreachable. Open tiles touching with `adjacency` count
+/// as connected.
+pub fn ensure_connectivity(
+    rooms: &[Room],
+    tile_map: &mut TileMap,
+    umap: &mut UMap8,
+    entrance: Coord,
+    constraints: Option<&UMap8>,
+    adjacency: Adjacency,
+) -> ConnectivityReport {
+    let r = tile_map.len();
+    let mut reached = vec![vec![false; r]; r];
+    let mut report = ConnectivityReport {
+        entrance,
+        ..Default::default()
+    };
+
+    reached[entrance.x][entrance.y] = true;
+    flood(tile_map, &mut reached, vec![entrance], adjacency);
+
+    let is_reached = |room: &Room, reached: &[Vec<bool>]| room.tiles.iter().any(|t| reached[t.x][t.y]);
+    let mut pending: Vec<usize> = (0..rooms.len()).filter(|&i| !is_reached(&rooms[i], &reached)).collect();
+
+    while !pending.is_empty() {
+        let anchors: Vec<usize> = (0..rooms.len()).filter(|&i| is_reached(&rooms[i], &reached)).collect();
+
+        // the pending room closest to the entrance or any reached room
+        let (slot, from) = pending
+            .iter()
+            .enumerate()
+            .map(|(slot, &i)| {
+                let target = rooms[i].center;
+                let nearest = anchors
+                    .iter()
+                    .map(|&a| (Some(a), dist_squared(&rooms[a].center, &target)))
+                    .chain(std::iter::once((None, dist_squared(&entrance, &target))))
+                    .min_by_key(|(_, d)| *d)
+                    .expect("the entrance is always an anchor");
+                (slot, nearest)
+            })
+            .min_by_key(|(_, (_, d))| *d)
+            .map(|(slot, (from, _))| (slot, from))
+            .expect("pending is not empty");
+
+        let target = &rooms[pending.swap_remove(slot)];
+        let (start, end) = match from {
+            Some(a) => closest_tiles(&rooms[a], target),
+            None => (entrance, closest_entry_tile(target, &entrance)),
+        };
+
+        // only the part of the tunnel touching the reached area extends it
+        let carved = carve_tunnel(start, end, tile_map, umap, constraints);
+        let seeds: Vec<Coord> = carved
+            .into_iter()
+            .filter(|c| reached[c.x][c.y] || neighbour_coords(c, adjacency, r).iter().any(|n| reached[n.x][n.y]))
+            .collect();
+        for c in &seeds {
+            reached[c.x][c.y] = true;
+        }
+        flood(tile_map, &mut reached, seeds, adjacency);
+
+        if is_reached(target, &reached) {
+            report.repaired.push(target.id);
+        } else {
+            report.unreachable.push(target.id);
+        }
+
+        // rooms the tunnel opened into on its way count as repaired too
+        pending.retain(|&i| {
+            let now_reached = is_reached(&rooms[i], &reached);
+            if now_reached {
+                report.repaired.push(rooms[i].id);
+            }
+            !now_reached
+        });
+    }
+
+    report
+}
+
+/// The surface tile of the planet for an entrance, or the given point
+pub fn resolve_entrance(entrance: &Entrance, planet: &UMap8) -> Coord {
+    let r = planet.len();
+    match *entrance {
+        Entrance::Point(x, y) => Coord {
+            x: x.min(r - 1),
+            y: y.min(r - 1),
+        },
+        Entrance::Angle(angle) => {
+            // march outwards from the centre, keeping the last solid tile
+            let c = r as f32 / 2.;
+            let (dx, dy) = (angle.cos(), angle.sin());
+            let mut surface = Coord { x: r / 2, y: r / 2 };
+            let mut t = 0.;
+            while t < c {
+                let x = (c + dx * t) as usize;
+                let y = (c + dy * t) as usize;
+                if x >= r || y >= r {
+                    break;
+                }
+                if planet[x][y] == 1 {
+                    surface = Coord { x, y };
+                }
+                t += 0.5;
+            }
+            surface
+        }
+    }
+}
+
+pub fn is_open(tile: &Tile) -> bool {
+    matches!(
+        tile,
+        Tile::Room(_) | Tile::RoomEdge(_) | Tile::RoomCenter(_) | Tile::Tunnel(_)
+    )
+}
+
+/// continues a flood fill over open tiles from the seeds
+fn flood(tile_map: &TileMap, reached: &mut [Vec<bool>], seeds: Vec<Coord>, adjacency: Adjacency) {
+    let r = tile_map.len();
+    let mut queue: VecDeque<Coord> = seeds.into();
+
+    while let Some(c) = queue.pop_front() {
+        for n in neighbour_coords(&c, adjacency, r) {
+            if reached[n.x][n.y] || !is_open(&tile_map[n.x][n.y]) {
+                continue;
+            }
+            reached[n.x][n.y] = true;
+            queue.push_back(n);
+        }
+    }
+}
+
+/// Carves a straight tunnel three tiles wide, wide enough to stay 4-connected
+/// through diagonal steps. Returns the tunnel tiles that are open afterwards.
+fn carve_tunnel(
+    start: Coord,
+    end: Coord,
+    tile_map: &mut TileMap,
+    umap: &mut UMap8,
+    constraints: Option<&UMap8>,
+) -> Vec<Coord> {
+    carve_path(simple_line(start, end), REPAIR_HALF_WIDTH, tile_map, umap, constraints)
+}
+
+/// Turns the walls in a square of `half_width` around every point of the
+/// path into tunnel and clears them from `umap`, so they stay open through
+/// the crust, skipping forced solid tiles. Returns the tiles around the path
+/// that are open afterwards.
+pub fn carve_path(
+    path: impl IntoIterator<Item = Coord>,
+    half_width: isize,
+    tile_map: &mut TileMap,
+    umap: &mut UMap8,
+    constraints: Option<&UMap8>,
+) -> Vec<Coord> {
+    let r = tile_map.len() as isize;
+    let mut carved = vec![];
+
+    for p in path {
+        for i in -half_width..=half_width {
+            for j in -half_width..=half_width {
+                let (x, y) = (p.x as isize + i, p.y as isize + j);
+                if x < 0 || y < 0 || x >= r || y >= r {
+                    continue;
+                }
+                let (x, y) = (x as usize, y as usize);
+                if constraints.is_some_and(|c| c[x][y] == CONSTRAINT_SOLID) {
+                    continue;
+                }
+                if let Tile::Wall(_) = tile_map[x][y] {
+                    tile_map[x][y] = Tile::Tunnel(0);
+                }
+                umap[x][y] = 0;
+                if is_open(&tile_map[x][y]) {
+                    carved.push(Coord { x, y });
+                }
+            }
+        }
+    }
+
+    carved
+}
+
+fn closest_entry_tile(room: &Room, to: &Coord) -> Coord {
+    room.get_entry_tiles()
+        .into_iter()
+        .min_by_key(|t| dist_squared(t, to))
+        .unwrap_or(room.center)
+}
+
+fn dist_squared(a: &Coord, b: &Coord) -> usize {
+    let dx = a.x.abs_diff(b.x);
+    let dy = a.y.abs_diff(b.y);
+    dx * dx + dy * dy
+}
@@ -42,7 +42,7 @@ impl TileMapDebug for Vec<Vec<Tile>> {
                 let tile = self[x][y];
                 let symbol = match tile {
                     Tile::Space => " ".to_string(),
-                    Tile::Wall => ".".to_string(),
+                    Tile::Wall(_) => ".".to_string(),
                     Tile::Surface => "*".to_string(),
                     Tile::Room(value) => match value {
                         Status::Designated(v) => format!("{:1} ", v).trim().to_string(),
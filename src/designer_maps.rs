@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+
+use crate::{
+    ca_mask::load_mask_image,
+    types::{Blank, FMap, UMap8},
+};
+
+pub const CONSTRAINT_NONE: u8 = 0;
+pub const CONSTRAINT_SOLID: u8 = 1;
+pub const CONSTRAINT_OPEN: u8 = 2;
+
+/// Loads a painted density map as a scale for the initial CA weight,
+/// twice the brightness so mid grey is neutral
+pub fn load_density_map(path: &Path, resolution: usize) -> Result<FMap> {
+    let mut density = load_mask_image(path, resolution)?;
+    for value in density.iter_mut().flatten() {
+        *value *= 2.;
+    }
+    Ok(density)
+}
+
+/// Forces the black areas of a density map solid, where painted
+/// constraints leave them free, so the CA cannot open caves there
+pub fn forbid_black(density: &FMap, constraints: &mut Option<UMap8>) {
+    let black: Vec<(usize, usize)> = density
+        .iter()
+        .enumerate()
+        .flat_map(|(x, column)| column.iter().enumerate().map(move |(y, d)| (x, y, *d)))
+        .filter(|&(_, _, d)| d <= 0.)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    if black.is_empty() {
+        return;
+    }
+
+    let constraints = constraints.get_or_insert_with(|| UMap8::blank(density.len()));
+    for (x, y) in black {
+        if constraints[x][y] == CONSTRAINT_NONE {
+            constraints[x][y] = CONSTRAINT_SOLID;
+        }
+    }
+}
+
+/// Loads a painted constraint map, resampled with nearest neighbour so the
+/// painted regions keep hard edges.
+///
+/// A pixel with red above half is forced solid, otherwise one with green
+/// above half is forced open. Transparent pixels carry no constraint.
+pub fn load_constraint_map(path: &Path, resolution: usize) -> Result<UMap8> {
+    let image = image::open(path)
+        .with_context(|| format!("failed to open constraint map {}", path.display()))?
+        .to_rgba8();
+    let resized = imageops::resize(&image, resolution as u32, resolution as u32, FilterType::Nearest);
+
+    Ok((0..resolution as u32)
+        .map(|x| {
+            (0..resolution as u32)
+                .map(|y| {
+                    let [r, g, _, a] = resized.get_pixel(x, y).0;
+                    if a < 128 {
+                        CONSTRAINT_NONE
+                    } else if r >= 128 {
+                        CONSTRAINT_SOLID
+                    } else if g >= 128 {
+                        CONSTRAINT_OPEN
+                    } else {
+                        CONSTRAINT_NONE
+                    }
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// multiplies the weight scale by the density map, either may be missing
+pub fn scale_by_density(scale: Option<FMap>, density: Option<FMap>) -> Option<FMap> {
+    match (scale, density) {
+        (Some(mut scale), Some(density)) => {
+            for (column, density) in scale.iter_mut().zip(&density) {
+                for (value, d) in column.iter_mut().zip(density) {
+                    *value *= d;
+                }
+            }
+            Some(scale)
+        }
+        (scale, density) => scale.or(density),
+    }
+}
+
+/// Overrides the CA output, where 1 is open cave, with the painted constraints
+pub fn apply_constraints(cave_map: &mut UMap8, constraints: &UMap8) {
+    for (column, constraints) in cave_map.iter_mut().zip(constraints) {
+        for (value, constraint) in column.iter_mut().zip(constraints) {
+            match *constraint {
+                CONSTRAINT_SOLID => *value = 0,
+                CONSTRAINT_OPEN => *value = 1,
+                _ => {}
+            }
+        }
+    }
+}
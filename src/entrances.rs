@@ -0,0 +1,160 @@
+use std::f32::consts::PI;
+
+use rand::{distributions::Uniform, rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    bit_map::simple_line,
+    connectivity::{carve_path, is_open, resolve_entrance},
+    room::Room,
+    tile_map::{Status, Tile, TileMap},
+    types::{CaveEntrance, Coord, Entrance, EntranceKind, FMap, RoomId, SurfaceEntranceOptions, UMap8},
+};
+
+/// Carves every configured opening from the surface contour down to the caves.
+///
+/// Caves where the depth field is above `sealed_depth` are filled by the
+/// crust later on, so every passage runs through the crust to a room tile
+/// whose depth is at most `sealed_depth`, and an opening with no such room
+/// to lead to is not carved. Must run after the
+/// rooms are found, and before `apply_materials` so the passages are treated
+/// like tunnels.
+pub fn carve_entrances(
+    rooms: &[Room],
+    tile_map: &mut TileMap,
+    umap: &mut UMap8,
+    depth: &FMap,
+    sealed_depth: f32,
+    options: &SurfaceEntranceOptions,
+    constraints: Option<&UMap8>,
+) -> Vec<CaveEntrance> {
+    let angles = if options.angles.is_empty() {
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let range = Uniform::new(-PI, PI);
+        (0..options.count).map(|_| rng.sample(range)).collect()
+    } else {
+        options.angles.clone()
+    };
+
+    let r = tile_map.len();
+    let half_width = (options.width / 2) as isize;
+
+    angles
+        .into_iter()
+        .map(|angle| {
+            let surface = resolve_entrance(&Entrance::Angle(angle), umap);
+
+            let path = match options.kind {
+                EntranceKind::Slope { max_grade } => {
+                    slope_path(surface, umap, rooms, depth, sealed_depth, max_grade)
+                }
+                EntranceKind::Shaft => None,
+            };
+            let path = path.or_else(|| shaft_path(surface, rooms, tile_map, depth, sealed_depth));
+
+            let room = match path {
+                Some((path, room)) => {
+                    carve_path(path, half_width, tile_map, umap, constraints);
+                    room
+                }
+                None => None,
+            };
+
+            CaveEntrance {
+                angle,
+                surface,
+                position: surface.into_world_normalized_vec2(&(r as u32)),
+                room,
+            }
+        })
+        .collect()
+}
+
+/// Straight to the closest entry tile of a room with a depth of at most
+/// `sealed_depth`, up to and including the first such open tile on the way.
+/// `None` when no room tile is that shallow.
+fn shaft_path(
+    surface: Coord,
+    rooms: &[Room],
+    tile_map: &TileMap,
+    depth: &FMap,
+    sealed_depth: f32,
+) -> Option<(Vec<Coord>, Option<RoomId>)> {
+    let target = rooms
+        .iter()
+        .flat_map(|room| room.get_entry_tiles())
+        .filter(|t| depth[t.x][t.y] <= sealed_depth)
+        .min_by_key(|t| t.x.abs_diff(surface.x).pow(2) + t.y.abs_diff(surface.y).pow(2))?;
+
+    let mut path = vec![];
+    for p in simple_line(surface, target) {
+        path.push(p);
+        if is_open(&tile_map[p.x][p.y]) && depth[p.x][p.y] <= sealed_depth {
+            return Some((path, room_id(&tile_map[p.x][p.y])));
+        }
+    }
+
+    Some((path, room_id(&tile_map[target.x][target.y])))
+}
+
+/// Interpolates in polar coordinates to the closest room tile that can be
+/// reached without dropping more than `max_grade` per tile around the planet,
+/// keeping every point at or below the surface
+fn slope_path(
+    surface: Coord,
+    planet: &UMap8,
+    rooms: &[Room],
+    depth: &FMap,
+    sealed_depth: f32,
+    max_grade: f32,
+) -> Option<(Vec<Coord>, Option<RoomId>)> {
+    let r = depth.len();
+    let c = r as f32 / 2.;
+    let polar = |p: &Coord| {
+        let (dx, dy) = (p.x as f32 - c, p.y as f32 - c);
+        (dy.atan2(dx), (dx * dx + dy * dy).sqrt())
+    };
+    let (a0, r0) = polar(&surface);
+
+    let (room, (da, rt), _) = rooms
+        .iter()
+        .flat_map(|room| {
+            // a prefab with doors is only entered through them
+            room.tiles
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| room.door_tile_indexes.is_empty() || room.door_tile_indexes.contains(i))
+                .map(move |(_, t)| (room, t))
+        })
+        .filter(|(_, t)| depth[t.x][t.y] <= sealed_depth)
+        .filter_map(|(room, t)| {
+            let (at, rt) = polar(t);
+            let da = (at - a0 + PI).rem_euclid(2. * PI) - PI;
+            let arc = da.abs() * (r0 + rt) / 2.;
+            let drop = r0 - rt;
+            (drop <= max_grade * arc).then_some((room, (da, rt), arc.hypot(drop)))
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))?;
+
+    let steps = ((da.abs() * r0).hypot(r0 - rt) * 2.).ceil().max(1.) as usize;
+    let path = (0..=steps)
+        .map(|k| {
+            let t = k as f32 / steps as f32;
+            let a = a0 + da * t;
+            let ceiling = polar(&resolve_entrance(&Entrance::Angle(a), planet)).1;
+            let d = (r0 + (rt - r0) * t).min(ceiling);
+            Coord {
+                x: (c + a.cos() * d).clamp(0., (r - 1) as f32) as usize,
+                y: (c + a.sin() * d).clamp(0., (r - 1) as f32) as usize,
+            }
+        })
+        .collect();
+
+    Some((path, Some(room.id)))
+}
+
+fn room_id(tile: &Tile) -> Option<RoomId> {
+    match tile {
+        Tile::Room(Status::Designated(id)) | Tile::RoomEdge(id) | Tile::RoomCenter(id) => Some(*id),
+        _ => None,
+    }
+}
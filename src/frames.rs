@@ -0,0 +1,53 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+
+use crate::{bit_map::umap_to_image_buffer, types::UMap8};
+
+/// Converts binary maps, such as recorded CA frames, to black and white images
+pub fn frames_to_images(frames: &[UMap8]) -> Vec<RgbaImage> {
+    frames.iter().map(umap_to_image_buffer).collect()
+}
+
+/// Writes each frame as `<prefix>_0000.png`, `<prefix>_0001.png`, ... into `dir`,
+/// creating it if needed, and returns the written paths
+pub fn write_png_sequence(frames: &[RgbaImage], dir: &Path, prefix: &str) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let path = dir.join(format!("{}_{:04}.png", prefix, i));
+            frame.save(&path)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Writes the frames as an endlessly looping animated gif
+pub fn write_gif(frames: &[RgbaImage], path: &Path, frame_delay_ms: u32) -> Result<()> {
+    if frames.is_empty() {
+        return Err(anyhow!("no frames to write to {:?}", path));
+    }
+
+    let mut encoder = GifEncoder::new(File::create(path)?);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(frames.iter().map(|image| {
+        Frame::from_parts(
+            image.clone(),
+            0,
+            0,
+            Delay::from_numer_denom_ms(frame_delay_ms, 1),
+        )
+    }))?;
+
+    Ok(())
+}
@@ -6,17 +6,29 @@ use std::{
 
 use crate::{
     bit_map::{
-        image_buffer_to_fmap, image_buffer_to_umap,
-        umap_to_fmap, MapOpps,
+        image_buffer_to_umap, umap_to_fmap,
     },
-    cellular_automata::simulate_ca,
+    ca_mask::build_ca_mask,
+    cellular_automata::{init_weight_scale, simulate_ca},
+    connectivity::{ensure_connectivity, resolve_entrance},
     debug_print::MapDebug,
+    designer_maps::{
+        apply_constraints, forbid_black, load_constraint_map, load_density_map, scale_by_density,
+        CONSTRAINT_SOLID,
+    },
+    entrances::carve_entrances,
     map_data::MapData,
-    noise_circle::simple_circle,
+    material_automata::simulate_material_ca,
+    noise_circle::{generate_surface_profiles, simple_circle},
+    portals::find_tunnel_portals,
+    prefab::place_prefabs,
     room::closest_tiles,
+    room_tags::tag_rooms,
     roooms::Roooms,
+    strata::{apply_materials, generate_material_map},
     tile_map::{FromUMap, Tile, TileMap},
     utils::random_distribution_mask_weighted,
+    warp::warp_map_data,
 };
 use anyhow::{anyhow, Result};
 use bit_map::{
@@ -28,8 +40,7 @@ use image::RgbaImage;
 use imageproc::point;
 use marching_squares::march_squares_rgba;
 use noise::{
-    permutationtable::PermutationTable, Clamp, Fbm,
-    NoiseFn, Perlin, Simplex,
+    permutationtable::PermutationTable, Clamp, Perlin,
 };
 use planet_data::PlanetData;
 use rayon::iter::{
@@ -44,29 +55,70 @@ use types::{
 };
 
 mod bit_map;
+mod ca_mask;
 mod cellular_automata;
+mod connectivity;
 mod debug_print;
+mod designer_maps;
+mod entrances;
+pub mod frames;
 mod map_data;
 mod marching_squares;
+mod material_automata;
 mod noise_circle;
 mod noise_example;
 pub mod planet_data;
+pub mod portals;
+pub mod prefab;
 pub mod room;
+mod room_center;
+pub mod room_ids;
+pub mod room_index;
+pub mod room_metrics;
+pub mod room_outline;
+mod room_split;
+pub mod room_tags;
 pub mod roooms;
+pub mod strata;
+mod summed_area;
 pub mod tile_map;
 mod traits;
 pub mod triangulation;
 pub mod types;
 mod utils;
+mod warp;
+
+/// the warped planet and its fields, before materials and caves
+fn initial_map_data(
+    options: &PlanetOptions,
+    fractal_options: Vec<&FractalNoiseOptions>,
+) -> Result<MapData> {
+    let mut md = MapData::default();
+    let v = get_initial_planet_map(options, fractal_options)?;
+    md.raw_map = v.0;
+    md.altitude_field = v.1;
+    md.depth_field = v.2;
+
+    warp_map_data(
+        &mut md,
+        &options.warp_options,
+        options.displacement_scale,
+        options.displacement_frequency,
+    );
+
+    Ok(md)
+}
 
 pub struct PlanetBuilder {
     hasher: PermutationTable,
+    seed: u32,
 }
 
 impl PlanetBuilder {
     pub fn new(seed: u32) -> Self {
         PlanetBuilder {
             hasher: PermutationTable::new(seed),
+            seed,
         }
     }
 
@@ -78,39 +130,39 @@ impl PlanetBuilder {
         tracing::info!("##### new planet #####");
 
         let r = options.resolution();
-        let mut md = MapData::default();
-        let v = get_initial_planet_map(
-            &options,
-            fractal_options,
-        )?;
-        md.raw_map = v.0;
-        md.altitude_field = v.1;
-        md.depth_field = v.2;
-
-        md.raw_map = warp(
+        let mut md = initial_map_data(&options, fractal_options)?;
+
+        md.material_map = generate_material_map(
             &md.raw_map,
-            options.displacement_scale,
-            options.displacement_frequency,
+            &md.depth_field,
+            &options.strata_options,
         );
-        md.raw_map = warp(
-            &md.raw_map,
-            options.displacement_scale,
-            options.displacement_frequency,
+        md.material_map = simulate_material_ca(
+            &md.material_map,
+            &md.depth_field,
+            &options.material_ca_options,
         );
 
         let mut map_main = UMap8::blank(r as usize);
         let mut tile_map = TileMap::blank(r as usize);
         let mut mask: Option<FMap> = None;
-        let blurred_edge = rgba_image_blur(
-            &umap_to_image_buffer(&md.raw_map),
-            options.ca_options.mask_options.mult,
-        );
-        let mut msk = image_buffer_to_fmap(&blurred_edge);
-
-        msk.clamp(0., 1.);
-        msk.invert();
-        msk.mult(2.);
-        msk.clamp(0., 1.);
+        let msk = build_ca_mask(&md, &options.ca_options.mask_options)?;
+
+        let density = options
+            .ca_options
+            .density_map
+            .as_ref()
+            .map(|path| load_density_map(path, r as usize))
+            .transpose()?;
+        let mut constraints = options
+            .ca_options
+            .constraint_map
+            .as_ref()
+            .map(|path| load_constraint_map(path, r as usize))
+            .transpose()?;
+        if let Some(density) = &density {
+            forbid_black(density, &mut constraints);
+        }
 
         let init_state = random_distribution_mask_weighted(
             options.resolution(),
@@ -118,14 +170,15 @@ impl PlanetBuilder {
             &msk,
             true,
             options.ca_options.seed,
+            scale_by_density(init_weight_scale(&options.ca_options, &md), density).as_ref(),
         );
 
         mask = Some(msk);
 
         match &options.rooms {
             true => {
-                let mut cave_map_raw: UMap8 =
-                    simulate_ca(&options, init_state, &md);
+                let (mut cave_map_raw, ca_frames) =
+                    simulate_ca(&options, init_state, &md)?;
                 let center_hole =
                     simple_circle(options.radius * 10., r);
                 for y in 0..r {
@@ -140,21 +193,48 @@ impl PlanetBuilder {
                     }
                 }
 
+                if let Some(constraints) = &constraints {
+                    apply_constraints(&mut cave_map_raw, constraints);
+                }
+
+                let prefabs = place_prefabs(
+                    &options.prefabs,
+                    &mut cave_map_raw,
+                    &md.raw_map,
+                    &md.depth_field,
+                    &mut constraints,
+                    options.room_adjacency,
+                )?;
+
                 tile_map = TileMap::from_planet_and_caves(
                     &md.raw_map,
                     &cave_map_raw,
                 );
-                let roooms =
-                    Roooms::new(&mut tile_map).ok();
+                let mut roooms = Roooms::with_prefabs(
+                    &mut tile_map,
+                    &prefabs,
+                    options.min_room_size,
+                    options.max_room_size,
+                    options.room_segmentation,
+                    options.room_center,
+                    options.room_adjacency,
+                )
+                .ok();
+                if let Some(roooms) = &mut roooms {
+                    roooms.identify(self.seed, tile_map.len());
+                    roooms.measure(&md.depth_field, &md.altitude_field);
+                    roooms.build_graph(&options.room_graph);
+                }
 
                 if let Some(roooms) = &roooms {
-                    if let Some(mst) = roooms.mst.as_ref() {
+                    if let Some(graph) = roooms.graph.as_ref() {
                         if options.tunnels {
                             match connect_rooms(
                                 &roooms.rooms,
-                                mst,
+                                graph,
                                 &mut tile_map,
                                 &mut md.raw_map,
+                                constraints.as_ref(),
                             ) {
                                 Ok(_) => {}
                                 Err(e) => {
@@ -167,6 +247,53 @@ impl PlanetBuilder {
                     }
                 }
 
+                let entrances = match &roooms {
+                    Some(roooms) => carve_entrances(
+                        &roooms.rooms,
+                        &mut tile_map,
+                        &mut md.raw_map,
+                        &md.depth_field,
+                        1. - options.crust_thickness,
+                        &options.entrances,
+                        constraints.as_ref(),
+                    ),
+                    None => vec![],
+                };
+
+                let connectivity = match (&roooms, &options.connectivity.entrance) {
+                    (Some(roooms), Some(entrance)) => {
+                        let entrance = resolve_entrance(entrance, &md.raw_map);
+                        let report = ensure_connectivity(
+                            &roooms.rooms,
+                            &mut tile_map,
+                            &mut md.raw_map,
+                            entrance,
+                            constraints.as_ref(),
+                            options.room_adjacency,
+                        );
+                        if !report.unreachable.is_empty() {
+                            tracing::warn!(
+                                "rooms unreachable from the entrance: {:?}",
+                                report.unreachable
+                            );
+                        }
+                        Some(report)
+                    }
+                    _ => None,
+                };
+
+                if let Some(roooms) = &mut roooms {
+                    roooms.portals.extend(find_tunnel_portals(&tile_map));
+
+                    let entrance = connectivity
+                        .as_ref()
+                        .map(|report| report.entrance)
+                        .or(entrances.first().map(|e| e.surface));
+                    tag_rooms(roooms, &options.room_tags, entrance);
+                }
+
+                apply_materials(&mut tile_map, &md.material_map);
+
                 map_main = thresh_sub(
                     &cave_map_raw,
                     &md.raw_map,
@@ -174,12 +301,6 @@ impl PlanetBuilder {
                     1. - options.crust_thickness, //todo don't do thickness like this, do it before rooms are calculated
                 );
 
-                map_main = warp(
-                    &map_main,
-                    options.displacement_scale,
-                    options.displacement_frequency,
-                );
-
                 let mut image =
                     umap_to_image_buffer(&map_main);
 
@@ -197,6 +318,7 @@ impl PlanetBuilder {
                     depth: md.depth_field,
                     edge_distance_field: None,
                     mask,
+                    materials: md.material_map,
                 };
 
                 return Ok(PlanetData {
@@ -206,10 +328,17 @@ impl PlanetBuilder {
                     tile_map,
                     mst: None,
                     roooms: roooms,
+                    ca_frames: options
+                        .ca_options
+                        .record_frames
+                        .then_some(ca_frames),
+                    connectivity,
+                    entrances,
                 });
             }
             false => {
                 tile_map = TileMap::from_u_map(&md.raw_map);
+                apply_materials(&mut tile_map, &md.material_map);
                 map_main = md.raw_map;
             }
         }
@@ -229,6 +358,7 @@ impl PlanetBuilder {
             depth: md.depth_field,
             edge_distance_field: None,
             mask,
+            materials: md.material_map,
         };
 
         Ok(PlanetData {
@@ -238,73 +368,37 @@ impl PlanetBuilder {
             tile_map,
             mst: None,
             roooms: None,
+            ca_frames: None,
+            connectivity: None,
+            entrances: vec![],
         })
     }
-}
 
-fn warp(
-    map: &Vec<Vec<u8>>,
-    scale: f64,
-    frequency: f64,
-) -> Vec<Vec<u8>> {
-    // println!("new ");
-
-    let fmb = Fbm::<Simplex>::new(0);
-    // let fmb = Fbm::<Perlin>::new(0);
-    let r = map.len();
-    let mut out: Vec<Vec<u8>> = vec![vec![0; r]; r]; // Creates a r x r matrix filled with 0s
-    // let out = Arc::new(
-    //     (0..r)
-    //         .map(|_| UnsafeCell::new(vec![0u8; r]))
-    //         .collect::<Vec<_>>(),
-    // );
-
-    // let instant: Instant = Instant::now();
-
-    // (0..r).into_par_iter().for_each(|x| {
-    //     // SAFETY: This is safe because each thread is modifying a unique part of the vector.
-    //     let out_x = unsafe { &mut *out[x].get() };
-    //     for y in 0..r {
-    //         let point1 = [x as f64 * frequency, y as f64 * frequency];
-    //         let point2 = [x as f64 * frequency, y as f64 * frequency + 100.0];
-
-    //         let offset1 = ((fmb.get(point1)) * scale) as i32;
-    //         let offset2 = ((fmb.get(point2)) * scale) as i32;
-
-    //         let new_x = (x as i32 + offset1).clamp(0, r as i32 - 1);
-    //         let new_y = (y as i32 + offset2).clamp(0, r as i32 - 1);
-
-    //         out_x[y] = map[new_x as usize][new_y as usize];
-    //     }
-    // });
-
-    for x in 0..r {
-        for y in 0..r {
-            let point1 = [
-                x as f64 * frequency,
-                y as f64 * frequency,
-            ];
-            let point2 = [
-                x as f64 * frequency,
-                y as f64 * frequency + 100.,
-            ];
-
-            let offset1 =
-                ((fmb.get(point1)) * scale) as i32;
-            let offset2 =
-                ((fmb.get(point2)) * scale) as i32;
-
-            let new_x =
-                (x as i32 + offset1).clamp(0, r as i32 - 1);
-            let new_y =
-                (y as i32 + offset2).clamp(0, r as i32 - 1);
-
-            out[x][y] = map[new_x as usize][new_y as usize];
-        }
+    /// Runs only the stages up to the CA mask and returns the mask, to preview
+    /// `CaOptions::mask_options` without simulating caves.
+    pub fn preview_ca_mask(
+        &self,
+        options: &PlanetOptions,
+        fractal_options: Vec<&FractalNoiseOptions>,
+    ) -> Result<FMap> {
+        let md = initial_map_data(options, fractal_options)?;
+        build_ca_mask(&md, &options.ca_options.mask_options)
+    }
 
+    /// Regenerates only the surface radius profile, once per time value.
+    ///
+    /// Each profile holds the surface radius in pixels at `samples` evenly
+    /// spaced angles. Use with `GlobalNoiseOptions::loop_period` for seamless
+    /// loops.
+    pub fn surface_profiles(
+        &self,
+        options: &PlanetOptions,
+        fractal_options: Vec<&FractalNoiseOptions>,
+        times: &[f64],
+        samples: usize,
+    ) -> Vec<Vec<f32>> {
+        generate_surface_profiles(options, fractal_options, times, samples)
     }
-    // println!("warp took {:?}", instant.elapsed());
-    out
 }
 
 trait mult {
@@ -415,15 +509,16 @@ fn mult(
 
 fn connect_rooms(
     rooms: &Vec<Room>,
-    mst: &Vec<(usize, usize)>,
+    graph: &Vec<(usize, usize)>,
     tile_map: &mut TileMap,
     umap: &mut UMap8,
+    constraints: Option<&UMap8>,
 ) -> Result<()> {
     let max_p = tile_map.len();
 
     let tunnel_thickness = umap.len() as f32 * 0.008;
 
-    for index_pair in mst {
+    for index_pair in graph {
         line_between_rooms(
             &rooms[index_pair.0],
             &rooms[index_pair.1],
@@ -433,9 +528,11 @@ fn connect_rooms(
         .try_for_each(|p| {
             if p.x > max_p - 1 || p.y > max_p - 1 {
                 Err(anyhow!("p.x > max_p || p.y > max_p"))
+            } else if constraints.is_some_and(|c| c[p.x][p.y] == CONSTRAINT_SOLID) {
+                Ok(())
             } else {
                 match tile_map[p.x][p.y] {
-                    Tile::Wall => {
+                    Tile::Wall(_) => {
                         tile_map[p.x][p.y] =
                             Tile::Tunnel(0);
                         umap[p.x][p.y] = 0;
@@ -6,6 +6,7 @@ pub struct MapData{
     pub altitude_field: FMap,
     pub depth_field: FMap,
     pub surface_distance_field: FMap,
+    pub material_map: UMap8,
 }
 
 // pub tile_map: TileMap
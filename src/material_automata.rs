@@ -0,0 +1,147 @@
+use rand::{distributions::Uniform, rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::{
+    cellular_automata::precompute_circle_offsets,
+    strata::MATERIAL_NONE,
+    types::{MaterialCaOptions, MaterialState, MaterialTransition, UMap8},
+};
+
+/// Grows material bodies through the layer materials with a multi-state CA.
+///
+/// Tiles start as the material of their layer (rock) and are seeded at random
+/// with the other states. Each iteration a tile takes the first transition
+/// from its current state whose weighted neighbour score reaches the
+/// transition's threshold, otherwise it keeps its state.
+pub fn simulate_material_ca(layers: &UMap8, depth: &[Vec<f32>], options: &MaterialCaOptions) -> UMap8 {
+    if options.seeds.is_empty() && options.transitions.is_empty() {
+        return layers.clone();
+    }
+
+    let mut map1 = seed_materials(layers, depth, options);
+    let mut map2 = map1.clone();
+    let offsets = precompute_circle_offsets(options.search_radius.max(1));
+
+    for _ in 0..options.iterations {
+        map2.par_iter_mut().enumerate().for_each(|(x, row)| {
+            for (y, tile) in row.iter_mut().enumerate() {
+                *tile = next_material(x, y, &map1, layers, options, &offsets);
+            }
+        });
+
+        std::mem::swap(&mut map1, &mut map2);
+    }
+
+    map1
+}
+
+fn seed_materials(layers: &UMap8, depth: &[Vec<f32>], options: &MaterialCaOptions) -> UMap8 {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let range = Uniform::new(0.0, 1.0);
+    let mut out = layers.clone();
+
+    for (x, row) in out.iter_mut().enumerate() {
+        for (y, tile) in row.iter_mut().enumerate() {
+            let roll: f32 = rng.sample(range);
+            if *tile == MATERIAL_NONE {
+                continue;
+            }
+
+            let d = depth[x][y];
+            let mut cumulative = 0.;
+            for seed in &options.seeds {
+                if d < seed.min_depth || d > seed.max_depth {
+                    continue;
+                }
+                cumulative += seed.probability;
+                if roll < cumulative {
+                    *tile = seed.material;
+                    break;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn next_material(
+    x: usize,
+    y: usize,
+    map: &UMap8,
+    layers: &UMap8,
+    options: &MaterialCaOptions,
+    offsets: &[(i32, i32)],
+) -> u8 {
+    let current = map[x][y];
+    if current == MATERIAL_NONE {
+        return current;
+    }
+
+    let rock = layers[x][y];
+    let current_state = state_of(current, rock);
+
+    options
+        .transitions
+        .iter()
+        .filter(|t| t.from == current_state)
+        .find(|t| neighbour_score(x, y, map, layers, t, offsets) >= t.threshold)
+        .map(|t| match t.to {
+            MaterialState::Rock => rock,
+            MaterialState::Material(m) => m,
+        })
+        .unwrap_or(current)
+}
+
+fn state_of(material: u8, rock: u8) -> MaterialState {
+    if material == rock {
+        MaterialState::Rock
+    } else {
+        MaterialState::Material(material)
+    }
+}
+
+/// mean weight of the neighbours inside the planet, excluding the tile itself
+fn neighbour_score(
+    x: usize,
+    y: usize,
+    map: &UMap8,
+    layers: &UMap8,
+    transition: &MaterialTransition,
+    offsets: &[(i32, i32)],
+) -> f32 {
+    let r = map.len() as i32;
+    let mut sum = 0.;
+    let mut count = 0;
+
+    for &(dx, dy) in offsets {
+        if dx == 0 && dy == 0 {
+            continue;
+        }
+
+        let nx = x as i32 + dx;
+        let ny = y as i32 + dy;
+        if nx < 0 || ny < 0 || nx >= r || ny >= r {
+            continue;
+        }
+
+        let neighbour = map[nx as usize][ny as usize];
+        if neighbour == MATERIAL_NONE {
+            continue;
+        }
+
+        let state = state_of(neighbour, layers[nx as usize][ny as usize]);
+        sum += transition
+            .weights
+            .iter()
+            .find(|(s, _)| *s == state)
+            .map_or(0., |(_, w)| *w);
+        count += 1;
+    }
+
+    if count > 0 {
+        sum / count as f32
+    } else {
+        0.
+    }
+}
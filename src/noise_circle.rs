@@ -1,10 +1,13 @@
 
+use std::f32::consts::PI;
+
 use anyhow::Result;
 use noise::{Fbm, MultiFractal, NoiseFn, Perlin, Simplex};
 
 use crate::{
-    types::{Blank, FMap, FractalNoiseOptions, UMap8},
+    types::{Blank, FMap, FractalNoiseOptions, GlobalNoiseOptions, UMap8},
     utils::{ang, circular_coord, dist, mapf64},
+    PlanetOptions,
 };
 
 pub fn simple_circle(radius: f32, resolution: u32) -> UMap8 {
@@ -24,10 +27,9 @@ pub fn generate_fbm_circle(
     noise_options: Vec<&FractalNoiseOptions>,
     mask_frequency: f64,
     mask_z: f64,
-    global_amplitude: f32,
+    global_options: &GlobalNoiseOptions,
     displacement_scale: f64,
     displacement_frequency: f64,
-    global_frequency: f32,
 ) -> Result<(UMap8, FMap, FMap)> {
     // let instant: Instant = Instant::now();
     let radius = resolution as f32 * 0.4 * radius as f32;
@@ -43,18 +45,20 @@ pub fn generate_fbm_circle(
         0,
         displacement_scale,
         displacement_frequency,
-        global_frequency as f64,
+        global_options,
     );
 
     for x in 0..resolution {
         for y in 0..resolution {
             let s = ang((x, y), center);
-            let (a, b) = circular_coord(s, 1.);
-            let noise_offset = noise_combiner.get([a as f64, b as f64], mask_frequency, mask_z)
-                as f32
-                * resolution as f32
-                * 0.5
-                * global_amplitude;
+            let noise_offset = surface_offset(
+                &noise_combiner,
+                s,
+                resolution,
+                mask_frequency,
+                mask_z,
+                global_options,
+            );
 
             let dist = dist(center, (x, y));
             let altitude = dist / radius;
@@ -77,6 +81,74 @@ pub fn generate_fbm_circle(
     Ok((map, altitude_field, depth_field))
 }
 
+/// Samples the surface radius, in pixels, at `samples` evenly spaced angles
+/// for each of the given times, without rebuilding the rest of the planet.
+///
+/// Angles follow the same convention as `generate_fbm_circle`, so sample `i`
+/// is the surface at `utils::ang(pixel, center) == i * 2π / samples - π`. The
+/// `time` in the global noise options is ignored in favour of `times`.
+pub fn generate_surface_profiles(
+    options: &PlanetOptions,
+    noise_options: Vec<&FractalNoiseOptions>,
+    times: &[f64],
+    samples: usize,
+) -> Vec<Vec<f32>> {
+    let resolution = options.resolution();
+    let radius = resolution as f32 * 0.4 * options.radius;
+    let noise_combiner = FbmCombiner::new(
+        noise_options,
+        0,
+        options.displacement_scale,
+        options.displacement_frequency,
+        &options.global_noise_options,
+    );
+
+    times
+        .iter()
+        .map(|&time| {
+            let global_options = GlobalNoiseOptions {
+                time,
+                ..options.global_noise_options.clone()
+            };
+            (0..samples)
+                .map(|i| {
+                    let angle = i as f32 / samples as f32 * 2. * PI - PI;
+                    radius
+                        - surface_offset(
+                            &noise_combiner,
+                            angle,
+                            resolution,
+                            options.noise_mask_options.mask_frequency,
+                            options.noise_mask_options.mask_z,
+                            &global_options,
+                        )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// how far the surface is pulled in from the base radius at the given angle, in pixels
+fn surface_offset(
+    noise_combiner: &FbmCombiner,
+    angle: f32,
+    resolution: u32,
+    mask_frequency: f64,
+    mask_z: f64,
+    global_options: &GlobalNoiseOptions,
+) -> f32 {
+    let (a, b) = circular_coord(angle, 1.);
+    noise_combiner.get(
+        [a as f64, b as f64],
+        global_options.time,
+        mask_frequency,
+        mask_z,
+    ) as f32
+        * resolution as f32
+        * 0.5
+        * global_options.amplitude
+}
+
 // struct FbmCombiner {
 //     fbm_vec: Vec<Fbm<Perlin>>,
 //     mask_noise: Perlin,
@@ -96,6 +168,9 @@ struct FbmCombiner {
     displacement_frequency: f64,
     amplitudes: Vec<f32>,
     offsets: Vec<f32>,
+    zs: Vec<f64>,
+    global_z: f64,
+    loop_period: f64,
 }
 
 impl FbmCombiner {
@@ -104,7 +179,7 @@ impl FbmCombiner {
         seed: u32,
         displacement_scale: f64,
         displacement_frequency: f64,
-        global_frequency: f64,
+        global_options: &GlobalNoiseOptions,
     ) -> Self {
         let displacement_noise_x = Simplex::new(seed + 1);
         let displacement_noise_y = Simplex::new(seed + 2);
@@ -114,7 +189,7 @@ impl FbmCombiner {
                 .iter()
                 .map(|options| {
                     Fbm::<Simplex>::new(seed)
-                        .set_frequency(options.frequency * global_frequency)
+                        .set_frequency(options.frequency * global_options.frequency as f64)
                         .set_persistence(options.persistence)
                         .set_lacunarity(options.lacunarity)
                         .set_octaves(options.octaves)
@@ -127,10 +202,37 @@ impl FbmCombiner {
             displacement_frequency,
             amplitudes: options_vec.iter().map(|x| x.amplitude).collect(),
             offsets: options_vec.iter().map(|x| x.offset).collect(),
+            zs: options_vec.iter().map(|x| x.z).collect(),
+            global_z: global_options.z,
+            loop_period: global_options.loop_period,
         }
     }
 
-    fn get(&self, point: [f64; 2], mask_freq: f64, mask_z: f64) -> f64 {
+    /// Samples a single fractal layer at a point on the unit circle.
+    ///
+    /// Time moves the sample along the z axis of 3d noise. With a loop period
+    /// the sample instead travels around a circle in the zw plane of 4d noise,
+    /// so the animation repeats seamlessly every `loop_period`.
+    fn get_layer(&self, i: usize, point: [f64; 2], time: f64) -> f64 {
+        let z = self.global_z + self.zs[i];
+
+        let value = if self.loop_period > 0. {
+            let loop_radius = self.loop_period / (2. * std::f64::consts::PI);
+            let t = time / self.loop_period * 2. * std::f64::consts::PI;
+            self.fbm_vec[i].get([
+                point[0],
+                point[1],
+                z + t.cos() * loop_radius,
+                t.sin() * loop_radius,
+            ])
+        } else {
+            self.fbm_vec[i].get([point[0], point[1], z + time])
+        };
+
+        value * self.amplitudes[i] as f64 + self.offsets[i] as f64
+    }
+
+    fn get(&self, point: [f64; 2], time: f64, mask_freq: f64, mask_z: f64) -> f64 {
         // let displaced_point = [
         //     point[0]
         //         + self.displacement_noise_x.get([
@@ -146,7 +248,7 @@ impl FbmCombiner {
 
         match self.fbm_vec.len() {
             0 => 0.0,
-            1 => (self.fbm_vec[0].get(point) * self.amplitudes[0] as f64) + self.offsets[0] as f64,
+            1 => self.get_layer(0, point, time),
             _ => {
                 let interval = 1.0 / (self.fbm_vec.len() - 1) as f64;
                 let mask_point = [mask_freq * point[0], mask_freq * point[1] + mask_z];
@@ -155,25 +257,21 @@ impl FbmCombiner {
 
                 let mut total_value = 0.0;
 
-                for (i, fbm) in self.fbm_vec.iter().enumerate() {
+                for i in 0..self.fbm_vec.len() {
                     if i < self.fbm_vec.len() - 1 {
                         // Calculate the blend factor based on the mask's position within the interval
                         let lower_bound = i as f64 * interval;
                         let upper_bound = (i + 1) as f64 * interval;
                         if mask >= lower_bound && mask < upper_bound {
                             let blend_factor = (mask - lower_bound) / interval;
-                            let noise1 = fbm.get(point) * (self.amplitudes[i] as f64)
-                                + self.offsets[i] as f64;
-                            let noise2 = self.fbm_vec[i + 1].get(point)
-                                * (self.amplitudes[i + 1] as f64)
-                                + self.offsets[i + 1] as f64;
+                            let noise1 = self.get_layer(i, point, time);
+                            let noise2 = self.get_layer(i + 1, point, time);
                             total_value += noise1 * (1.0 - blend_factor) + noise2 * blend_factor;
                             break;
                         }
                     } else if mask >= i as f64 * interval {
                         // Handle the last interval
-                        total_value +=
-                            (fbm.get(point) * self.amplitudes[i] as f64) + self.offsets[i] as f64;
+                        total_value += self.get_layer(i, point, time);
                     }
                 }
 
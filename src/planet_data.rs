@@ -1,8 +1,8 @@
 use glam::{Vec2, Vec3};
-use image::{ImageBuffer, Rgba};
+use image::{ImageBuffer, Rgba, RgbaImage};
 use anyhow::{anyhow, Result};
 
-use crate::{roooms::Roooms, tile_map::TileMap, types::{Coord, PlanetMap}};
+use crate::{bit_map::{fmap_to_image_buffer, MapOpps}, frames::frames_to_images, roooms::Roooms, tile_map::TileMap, types::{CaveEntrance, ConnectivityReport, Coord, PlanetMap, UMap8}};
 
 pub use crate::marching_squares::march_squares_rgba;
 
@@ -15,6 +15,13 @@ pub struct PlanetData {
     pub tile_map: TileMap,
     pub mst: Option<Vec<(Coord, Coord)>>,
     pub roooms: Option<Roooms>,
+    /// the CA state after every iteration, when `CaOptions::record_frames` is set
+    pub ca_frames: Option<Vec<UMap8>>,
+    /// which rooms needed repair to be reached from the entrance, when
+    /// `ConnectivityOptions::entrance` is set
+    pub connectivity: Option<ConnectivityReport>,
+    /// the surface openings carved for `PlanetOptions::entrances`
+    pub entrances: Vec<CaveEntrance>,
 }
 
 impl PlanetData {
@@ -33,6 +40,23 @@ impl PlanetData {
 
             self.planet_map.resolution            
         }
+
+    /// the CA mask as a grayscale image, for previewing `CaMaskOptions`
+    pub fn mask_image(&self) -> Option<RgbaImage> {
+        self.planet_map.mask.as_ref().map(|mask| {
+            let mut mask = mask.clone();
+            mask.clamp(0., 1.);
+            fmap_to_image_buffer(&mask)
+        })
+    }
+
+    /// the recorded CA frames as images, empty if none were recorded
+    pub fn ca_frame_images(&self) -> Vec<RgbaImage> {
+        self.ca_frames
+            .as_ref()
+            .map(|frames| frames_to_images(frames))
+            .unwrap_or_default()
+    }
     }
 
 
@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use glam::Vec2;
+
+use crate::{
+    room::Room,
+    tile_map::{Status, Tile, TileMap},
+    types::{Adjacency, Coord, RoomId},
+};
+
+/// a touching tile and the step from it into the other room
+type Contact = (Coord, (i32, i32));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortalKind {
+    /// two rooms touching directly, where one area was split into several
+    Adjacent,
+    /// a tunnel opening into a room
+    Tunnel,
+}
+
+/// Where a room opens into another room or into a tunnel
+#[derive(Clone, Debug)]
+pub struct Portal {
+    pub kind: PortalKind,
+    /// the room the portal belongs to, and the room on the other side. For
+    /// adjacent rooms the lowest id comes first. For tunnels the second room
+    /// is the closest one along the tunnel, `None` when the tunnel only
+    /// leads to the surface
+    pub rooms: (RoomId, Option<RoomId>),
+    /// tiles of the first room at the opening
+    pub tiles: Vec<Coord>,
+    /// middle of the opening in grid coordinates
+    pub position: Vec2,
+    /// number of tiles across the opening
+    pub width: usize,
+    /// unit vector pointing out of the first room
+    pub normal: Vec2,
+}
+
+/// Finds every opening where the tiles of two rooms touch, one portal per
+/// connected run of touching tiles
+pub fn find_room_portals(rooms: &[Room], resolution: usize) -> Vec<Portal> {
+    let mut owner: Vec<Vec<Option<usize>>> = vec![vec![None; resolution]; resolution];
+    for (i, room) in rooms.iter().enumerate() {
+        for t in &room.tiles {
+            owner[t.x][t.y] = Some(i);
+        }
+    }
+
+    // touching tiles of the lower indexed room
+    let mut contacts: BTreeMap<(usize, usize), Vec<Contact>> = BTreeMap::new();
+    for (i, room) in rooms.iter().enumerate() {
+        for t in &room.tiles {
+            for step in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let (x, y) = (t.x as i32 + step.0, t.y as i32 + step.1);
+                if x < 0 || y < 0 || x >= resolution as i32 || y >= resolution as i32 {
+                    continue;
+                }
+                if let Some(j) = owner[x as usize][y as usize] {
+                    if j > i {
+                        contacts.entry((i, j)).or_default().push((*t, step));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut portals = vec![];
+    for ((i, j), contact) in contacts {
+        let tiles: Vec<Coord> = contact.iter().map(|(t, _)| *t).collect();
+        for run in runs(&tiles) {
+            let (position, normal) = opening(&run, &contact);
+
+            portals.push(Portal {
+                kind: PortalKind::Adjacent,
+                rooms: (rooms[i].id.min(rooms[j].id), Some(rooms[i].id.max(rooms[j].id))),
+                width: run.len(),
+                tiles: run,
+                position,
+                normal: if rooms[i].id <= rooms[j].id { normal } else { -normal },
+            });
+        }
+    }
+
+    portals
+}
+
+/// Finds every opening where a tunnel enters a room, one portal per
+/// connected run of room tiles touching the same stretch of tunnel.
+///
+/// Tunnels are the 8-connected areas of tunnel tiles, so must be carved
+/// before this runs. Entrance passages show up as portals to the surface,
+/// while pockets of tunnel leading neither to another room nor to the
+/// surface are left out.
+pub fn find_tunnel_portals(tile_map: &TileMap) -> Vec<Portal> {
+    let r = tile_map.len();
+    let tunnels = tunnel_labels(tile_map);
+    let surface = surface_tunnels(tile_map, &tunnels);
+
+    // touching tiles of each room, per tunnel
+    let mut contacts: BTreeMap<(usize, RoomId), Vec<Contact>> = BTreeMap::new();
+    for (x, column) in tile_map.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            let Some(id) = room_id(tile) else {
+                continue;
+            };
+            let t = Coord { x, y };
+            for step in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                if let Some(n) = t.offset(step, r) {
+                    if let Some(tunnel) = tunnels[n.x][n.y] {
+                        contacts.entry((tunnel, id)).or_default().push((t, step));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut portals = vec![];
+    for ((tunnel, id), contact) in contacts {
+        let tiles: Vec<Coord> = contact.iter().map(|(t, _)| *t).collect();
+        for run in runs(&tiles) {
+            let (position, normal) = opening(&run, &contact);
+            let mouth: Vec<Coord> = contact
+                .iter()
+                .filter(|(t, _)| run.contains(t))
+                .filter_map(|(t, step)| t.offset(*step, r))
+                .collect();
+
+            let other = nearest_room_along(tile_map, &tunnels, tunnel, mouth, id);
+            if other.is_none() && !surface.contains(&tunnel) {
+                continue;
+            }
+
+            portals.push(Portal {
+                kind: PortalKind::Tunnel,
+                rooms: (id, other),
+                width: run.len(),
+                tiles: run,
+                position,
+                normal,
+            });
+        }
+    }
+
+    portals
+}
+
+/// middle of the opening and the mean step out of the room, for a run of tiles
+fn opening(run: &[Coord], contact: &[Contact]) -> (Vec2, Vec2) {
+    let run_set: HashSet<Coord> = run.iter().copied().collect();
+    let normal = contact
+        .iter()
+        .filter(|(t, _)| run_set.contains(t))
+        .fold(Vec2::ZERO, |acc, (_, s)| acc + Vec2::new(s.0 as f32, s.1 as f32))
+        .normalize_or_zero();
+    let position = run
+        .iter()
+        .fold(Vec2::ZERO, |acc, t| acc + Vec2::new(t.x as f32, t.y as f32))
+        / run.len() as f32
+        + normal * 0.5;
+    (position, normal)
+}
+
+fn room_id(tile: &Tile) -> Option<RoomId> {
+    match tile {
+        Tile::Room(Status::Designated(id)) | Tile::RoomEdge(id) | Tile::RoomCenter(id) => Some(*id),
+        _ => None,
+    }
+}
+
+/// 8-connected areas of tunnel tiles, numbered in scan order
+fn tunnel_labels(tile_map: &TileMap) -> Vec<Vec<Option<usize>>> {
+    let r = tile_map.len();
+    let mut labels = vec![vec![None; r]; r];
+    let mut count = 0;
+
+    for x in 0..r {
+        for y in 0..r {
+            if labels[x][y].is_some() || !matches!(tile_map[x][y], Tile::Tunnel(_)) {
+                continue;
+            }
+            labels[x][y] = Some(count);
+            let mut queue = VecDeque::from([Coord { x, y }]);
+            while let Some(c) = queue.pop_front() {
+                for &step in Adjacency::Eight.offsets() {
+                    if let Some(n) = c.offset(step, r) {
+                        if labels[n.x][n.y].is_none() && matches!(tile_map[n.x][n.y], Tile::Tunnel(_)) {
+                            labels[n.x][n.y] = Some(count);
+                            queue.push_back(n);
+                        }
+                    }
+                }
+            }
+            count += 1;
+        }
+    }
+
+    labels
+}
+
+/// tunnels with a tile next to open space outside the planet
+fn surface_tunnels(tile_map: &TileMap, tunnels: &[Vec<Option<usize>>]) -> HashSet<usize> {
+    let r = tile_map.len();
+    let mut out = HashSet::new();
+    for (x, column) in tunnels.iter().enumerate() {
+        for (y, label) in column.iter().enumerate() {
+            let Some(tunnel) = *label else {
+                continue;
+            };
+            let c = Coord { x, y };
+            let open = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                .into_iter()
+                .any(|step| c.offset(step, r).is_none_or(|n| tile_map[n.x][n.y] == Tile::Space));
+            if open {
+                out.insert(tunnel);
+            }
+        }
+    }
+    out
+}
+
+/// walks the tunnel breadth first from its mouth to the first other room it reaches
+fn nearest_room_along(
+    tile_map: &TileMap,
+    tunnels: &[Vec<Option<usize>>],
+    tunnel: usize,
+    mouth: Vec<Coord>,
+    from: RoomId,
+) -> Option<RoomId> {
+    let r = tile_map.len();
+    let mut seen: HashSet<Coord> = mouth.iter().copied().collect();
+    let mut queue: VecDeque<Coord> = mouth.into();
+
+    while let Some(c) = queue.pop_front() {
+        for &step in Adjacency::Eight.offsets() {
+            let Some(n) = c.offset(step, r) else {
+                continue;
+            };
+            match room_id(&tile_map[n.x][n.y]) {
+                Some(id) if id != from => return Some(id),
+                _ => {}
+            }
+            if tunnels[n.x][n.y] == Some(tunnel) && seen.insert(n) {
+                queue.push_back(n);
+            }
+        }
+    }
+
+    None
+}
+
+/// 8-connected runs of tiles, each tile listed once
+fn runs(tiles: &[Coord]) -> Vec<Vec<Coord>> {
+    let set: HashSet<Coord> = tiles.iter().copied().collect();
+    let mut seen: HashSet<Coord> = HashSet::new();
+    let mut out = vec![];
+
+    for &start in tiles {
+        if !seen.insert(start) {
+            continue;
+        }
+        let mut run = vec![];
+        let mut stack = vec![start];
+        while let Some(c) = stack.pop() {
+            run.push(c);
+            for dx in -1..=1i32 {
+                for dy in -1..=1i32 {
+                    let (x, y) = (c.x as i32 + dx, c.y as i32 + dy);
+                    if x < 0 || y < 0 {
+                        continue;
+                    }
+                    let n = Coord { x: x as usize, y: y as usize };
+                    if set.contains(&n) && seen.insert(n) {
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        out.push(run);
+    }
+
+    out
+}
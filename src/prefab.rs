@@ -0,0 +1,243 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    designer_maps::{CONSTRAINT_NONE, CONSTRAINT_SOLID},
+    types::{Adjacency, Blank, Coord, FMap, PrefabOptions, UMap8},
+};
+
+/// placement attempts per requested copy before giving up on it
+const PLACEMENT_ATTEMPTS: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StampCell {
+    /// left as the caves made it
+    Any,
+    Solid,
+    Open,
+    /// open floor where tunnels attach
+    Door,
+}
+
+/// stamp cells indexed [x][y]
+pub type Stamp = Vec<Vec<StampCell>>;
+
+/// A placed copy of a stamp, in map coordinates
+#[derive(Clone, Debug)]
+pub struct PrefabPlacement {
+    pub name: String,
+    /// every open and door tile
+    pub open: Vec<Coord>,
+    pub doors: Vec<Coord>,
+}
+
+/// Loads a PNG stamp, or a text stamp for any other extension
+pub fn load_stamp(path: &Path) -> Result<Stamp> {
+    let is_png = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+
+    if is_png {
+        let image = image::open(path)
+            .with_context(|| format!("failed to open stamp {}", path.display()))?
+            .to_rgba8();
+
+        Ok((0..image.width())
+            .map(|x| {
+                (0..image.height())
+                    .map(|y| {
+                        let [r, g, b, a] = image.get_pixel(x, y).0;
+                        if a < 128 {
+                            StampCell::Any
+                        } else if r >= 128 && g < 128 && b < 128 {
+                            StampCell::Door
+                        } else if r >= 128 {
+                            StampCell::Open
+                        } else {
+                            StampCell::Solid
+                        }
+                    })
+                    .collect()
+            })
+            .collect())
+    } else {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read stamp {}", path.display()))?;
+        parse_ascii_stamp(&text)
+    }
+}
+
+pub fn parse_ascii_stamp(text: &str) -> Result<Stamp> {
+    let rows: Vec<&str> = text.lines().collect();
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    let mut stamp = vec![vec![StampCell::Any; rows.len()]; width];
+
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            stamp[x][y] = match c {
+                ' ' => StampCell::Any,
+                '#' => StampCell::Solid,
+                '.' => StampCell::Open,
+                'D' => StampCell::Door,
+                other => return Err(anyhow!("unknown stamp marker {:?} at {}, {}", other, x, y)),
+            };
+        }
+    }
+
+    Ok(stamp)
+}
+
+/// Stamps the configured prefabs into the CA output, where 1 is open.
+///
+/// A copy is placed where all of its marked tiles are inside the planet,
+/// within its depth range, free of painted constraints and away from other
+/// copies. Its open tiles are wrapped in a one tile ring of rock, closed
+/// against every neighbour of `adjacency`, so the room stays separate from
+/// the caves around it, and its solid tiles are added
+/// to `constraints` so tunnels do not carve through them.
+pub fn place_prefabs(
+    options: &PrefabOptions,
+    cave_map: &mut UMap8,
+    planet: &UMap8,
+    depth: &FMap,
+    constraints: &mut Option<UMap8>,
+    adjacency: Adjacency,
+) -> Result<Vec<PrefabPlacement>> {
+    let r = cave_map.len();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut occupied: HashSet<Coord> = HashSet::new();
+    let mut placements = vec![];
+
+    for stamp_options in &options.stamps {
+        let stamp = load_stamp(&stamp_options.path)?;
+        let width = stamp.len();
+        let height = stamp.first().map_or(0, |column| column.len());
+        if width == 0 || height == 0 || width + 2 >= r || height + 2 >= r {
+            continue;
+        }
+
+        let marked: Vec<(usize, usize, StampCell)> = (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .map(|(x, y)| (x, y, stamp[x][y]))
+            .filter(|(_, _, cell)| *cell != StampCell::Any)
+            .collect();
+
+        let mut placed = 0;
+        for _ in 0..stamp_options.count * PLACEMENT_ATTEMPTS {
+            if placed == stamp_options.count {
+                break;
+            }
+
+            // keep one tile free on every side for the rock ring
+            let ox = rng.gen_range(1..r - width - 1);
+            let oy = rng.gen_range(1..r - height - 1);
+
+            let valid = marked.iter().all(|&(x, y, _)| {
+                let c = Coord { x: ox + x, y: oy + y };
+                planet[c.x][c.y] == 1
+                    && depth[c.x][c.y] >= stamp_options.min_depth
+                    && depth[c.x][c.y] <= stamp_options.max_depth
+                    && !occupied.contains(&c)
+                    && constraints
+                        .as_ref()
+                        .is_none_or(|m| m[c.x][c.y] == CONSTRAINT_NONE)
+            });
+            if !valid {
+                continue;
+            }
+
+            let constraints = constraints.get_or_insert_with(|| UMap8::blank(r));
+            let mut placement = PrefabPlacement {
+                name: stamp_options.name.clone(),
+                open: vec![],
+                doors: vec![],
+            };
+
+            for &(x, y, cell) in &marked {
+                let c = Coord { x: ox + x, y: oy + y };
+                occupied.insert(c);
+                match cell {
+                    StampCell::Solid => {
+                        cave_map[c.x][c.y] = 0;
+                        constraints[c.x][c.y] = CONSTRAINT_SOLID;
+                    }
+                    StampCell::Door => {
+                        cave_map[c.x][c.y] = 1;
+                        placement.open.push(c);
+                        placement.doors.push(c);
+                    }
+                    StampCell::Open => {
+                        cave_map[c.x][c.y] = 1;
+                        placement.open.push(c);
+                    }
+                    StampCell::Any => {}
+                }
+            }
+
+            let open: HashSet<Coord> = placement.open.iter().copied().collect();
+            for c in &placement.open {
+                for &step in adjacency.offsets() {
+                    let n = *c + step;
+                    if !open.contains(&n) {
+                        cave_map[n.x][n.y] = 0;
+                        occupied.insert(n);
+                    }
+                }
+            }
+
+            if !placement.open.is_empty() {
+                placements.push(placement);
+            }
+            placed += 1;
+        }
+
+        if placed < stamp_options.count {
+            tracing::warn!(
+                "placed {} of {} copies of prefab {}",
+                placed,
+                stamp_options.count,
+                stamp_options.name
+            );
+        }
+    }
+
+    Ok(placements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stamp_markers_indexed_by_x_then_y() {
+        let stamp = parse_ascii_stamp("#.D\n #.").unwrap();
+        assert_eq!(stamp.len(), 3);
+        assert_eq!(stamp[0], vec![StampCell::Solid, StampCell::Any]);
+        assert_eq!(stamp[1], vec![StampCell::Open, StampCell::Solid]);
+        assert_eq!(stamp[2], vec![StampCell::Door, StampCell::Open]);
+    }
+
+    #[test]
+    fn pads_ragged_rows_with_any() {
+        let stamp = parse_ascii_stamp("#\n###\n##").unwrap();
+        assert_eq!(stamp.len(), 3);
+        assert!(stamp.iter().all(|column| column.len() == 3));
+        assert_eq!(stamp[1][0], StampCell::Any);
+        assert_eq!(stamp[2][0], StampCell::Any);
+        assert_eq!(stamp[2][1], StampCell::Solid);
+        assert_eq!(stamp[2][2], StampCell::Any);
+    }
+
+    #[test]
+    fn rejects_unknown_markers() {
+        let error = parse_ascii_stamp("#.\n#x").unwrap_err();
+        assert!(error.to_string().contains("'x'"), "{}", error);
+    }
+
+    #[test]
+    fn empty_stamp_has_no_columns() {
+        assert!(parse_ascii_stamp("").unwrap().is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
-    bit_map::{average_center, edge_average_center, max_inscribed_circle}, debug_print::TileMapDebug, tile_map::{Status, Tile, TileMap}, types::Coord
+    debug_print::TileMapDebug, room_center::room_center, room_metrics::RoomMetrics, room_outline::{trace_outlines, RoomOutline}, strata::MATERIAL_NONE, tile_map::{Status, Tile, TileMap}, types::{Adjacency, CenterStrategy, Coord, RoomId, RoomTag}
 };
 use std::collections::{HashSet, VecDeque};
+use uuid::Uuid;
 
 #[derive(Clone, Default, Debug)]
 pub struct Room {
@@ -9,21 +10,37 @@ pub struct Room {
     pub tiles_hash: HashSet<Coord>,
     pub center: Coord,
     pub edge_tile_indexes: Vec<usize>,
-    pub id: u16,
+    /// indexes into `tiles` of the doors of a prefab, the only tiles tunnels
+    /// and entrances connect to when not empty
+    pub door_tile_indexes: Vec<usize>,
+    pub id: RoomId,
+    /// stable across generations, set by `Roooms::identify`
+    pub uid: Uuid,
+    /// name of the prefab stamp the room was placed from
+    pub prefab: Option<String>,
+    /// set once the rooms are measured, see `Roooms::measure`
+    pub metrics: Option<RoomMetrics>,
+    /// set by `room_tags::tag_rooms`
+    pub tags: Vec<RoomTag>,
 }
 
 impl Room {
-    pub fn new(tiles: Vec<Coord>, id: u16) -> Self {
+    pub fn new(tiles: Vec<Coord>, id: RoomId, adjacency: Adjacency) -> Self {
         let tiles_hash = tiles.iter().cloned().collect();
-        let edge_tile_indexes = Room::find_edges(&tiles, &tiles_hash);
-        let center = Room::calc_center(&tiles, &edge_tile_indexes);
+        let edge_tile_indexes = Room::find_edges(&tiles, &tiles_hash, adjacency);
+        let center = room_center(&tiles, &tiles_hash, CenterStrategy::default());
 
         Room {
             tiles,
             tiles_hash,
             center,
             edge_tile_indexes,
+            door_tile_indexes: vec![],
             id,
+            uid: Uuid::nil(),
+            prefab: None,
+            metrics: None,
+            tags: vec![],
         }
     }
 
@@ -34,8 +51,9 @@ impl Room {
     pub fn generate_room(
         search_start: (usize, usize),
         tile_map: &mut TileMap,
-        id: u16,
+        id: RoomId,
         min_room_size: usize,
+        adjacency: Adjacency,
     ) -> Option<Room> {
 
         let x = search_start.0;
@@ -63,7 +81,7 @@ impl Room {
                 y: tile.y,
             };
 
-            for adjacent_coord in get_adjacent_coords(&this_coord, res) {
+            for adjacent_coord in neighbour_coords(&this_coord, adjacency, res) {
                 if tile_map[adjacent_coord.x][adjacent_coord.y] != Tile::Room(Status::Undesignated) {
                     continue;
                 }
@@ -74,20 +92,28 @@ impl Room {
 
         // erase if below min size
         if results.len() < min_room_size {
-            results.iter().for_each(|c| tile_map[c.x][c.y] = Tile::Wall);
+            results.iter().for_each(|c| tile_map[c.x][c.y] = Tile::Wall(MATERIAL_NONE));
             return None;
         }
 
-        let new_room = Room::new(results, id);
+        let new_room = Room::new(results, id, adjacency);
+        new_room.mark(tile_map);
 
-        tile_map[new_room.center.x][new_room.center.y] = Tile::RoomCenter(id);
+        Some(new_room)
+    }
 
-        for edge_tile_index in &new_room.edge_tile_indexes {
-            let e = new_room.tiles[*edge_tile_index];
-            tile_map[e.x][e.y] = Tile::RoomEdge(id);
+    /// Writes the room's id into its tiles, edges and center on the tile map
+    pub fn mark(&self, tile_map: &mut TileMap) {
+        for tile in &self.tiles {
+            tile_map[tile.x][tile.y] = Tile::Room(Status::Designated(self.id));
         }
 
-        Some(new_room)
+        tile_map[self.center.x][self.center.y] = Tile::RoomCenter(self.id);
+
+        for edge_tile_index in &self.edge_tile_indexes {
+            let e = self.tiles[*edge_tile_index];
+            tile_map[e.x][e.y] = Tile::RoomEdge(self.id);
+        }
     }
 
     pub fn get_edge_tiles(&self) -> Vec<Coord> {
@@ -99,14 +125,34 @@ impl Room {
         edge_tiles
     }
 
-    fn find_edges(tiles: &Vec<Coord>, hash: &HashSet<Coord>) -> Vec<usize> {
-        get_edge_indexes(tiles, hash)
+    /// The tiles passages connect to, the doors when there are any and the
+    /// edge tiles otherwise
+    pub fn entry_tile_indexes(&self) -> &[usize] {
+        if self.door_tile_indexes.is_empty() {
+            &self.edge_tile_indexes
+        } else {
+            &self.door_tile_indexes
+        }
     }
 
-    fn calc_center(tiles: &Vec<Coord>, edges: &Vec<usize>) -> Coord {
-        // max_inscribed_circle(tiles, edges)
-        // edge_average_center(tiles, edges)
-        average_center(tiles)
+    pub fn get_entry_tiles(&self) -> Vec<Coord> {
+        self.entry_tile_indexes().iter().map(|i| self.tiles[*i]).collect()
+    }
+
+    fn find_edges(tiles: &Vec<Coord>, hash: &HashSet<Coord>, adjacency: Adjacency) -> Vec<usize> {
+        get_edge_indexes(tiles, hash, adjacency)
+    }
+
+    /// The boundary of the room in grid coordinates, traced with the
+    /// adjacency the room was filled with. See `RoomOutline::to_world` for
+    /// world coordinates
+    pub fn outlines(&self, adjacency: Adjacency) -> Vec<RoomOutline> {
+        trace_outlines(&self.tiles, &self.tiles_hash, adjacency)
+    }
+
+    /// Moves the center with another strategy, the tile map is left as is
+    pub fn recenter(&mut self, strategy: CenterStrategy) {
+        self.center = room_center(&self.tiles, &self.tiles_hash, strategy);
     }
 
     pub fn get_min_max_coords(&self) -> (Coord, Coord) {
@@ -145,14 +191,16 @@ impl Room {
     }
 }
 
-fn get_edge_indexes(tiles: &[Coord], hash: &HashSet<Coord>) -> Vec<usize> {
+/// Tiles with a neighbour outside the room, tiles on the map border included
+fn get_edge_indexes(tiles: &[Coord], hash: &HashSet<Coord>, adjacency: Adjacency) -> Vec<usize> {
     tiles
         .iter()
         .enumerate()
         .filter_map(|(i, c)| {
-            if get_neighbouring_coords_vertical_horizontal(c)
+            if adjacency
+                .offsets()
                 .iter()
-                .any(|n| !hash.contains(n))
+                .any(|&step| c.offset(step, usize::MAX).is_none_or(|n| !hash.contains(&n)))
             {
                 Some(i)
             } else {
@@ -162,27 +210,6 @@ fn get_edge_indexes(tiles: &[Coord], hash: &HashSet<Coord>) -> Vec<usize> {
         .collect()
 }
 
-fn get_neighbouring_coords_vertical_horizontal(c: &Coord) -> Vec<Coord> {
-    vec![*c + (1, 0), *c + (0, 1), *c + (0, -1), *c + (-1, 0)]
-}
-
-fn get_neighbouring_coords_all(c: &Coord) -> Vec<Coord> {
-    vec![
-        *c + (1, 0),
-        *c + (1, 1),
-        *c + (0, 1),
-        *c + (-1, 1),
-        *c + (0, -1),
-        *c + (-1, -1),
-        *c + (-1, 0),
-        *c + (1, -1),
-    ]
-}
-
-fn get_neighbouring_coords_diagonal(c: &Coord) -> Vec<Coord> {
-    vec![*c + (1, 1), *c + (-1, 1), *c + (-1, -1), *c + (1, -1)]
-}
-
 // fn get_center(tiles: &[Coord], edges: &[usize]) -> Coord {
 //     let edges_hash: HashSet<Coord> = edges.iter().map(|&i| tiles[i]).collect();
 //     let mut center = Coord::default();
@@ -222,9 +249,9 @@ fn get_neighbouring_coords_diagonal(c: &Coord) -> Vec<Coord> {
 //     center
 // }
 
-pub fn generate_rooms(tiles: &mut TileMap) -> Vec<Room> {
+pub fn generate_rooms(tiles: &mut TileMap, min_room_size: usize, adjacency: Adjacency) -> Vec<Room> {
     let res = tiles.len();
-    let mut room_counter: u16 = 0;
+    let mut room_counter: RoomId = 0;
     let mut rooms: Vec<Room> = Vec::new();
 
     tiles.debug_print();
@@ -232,7 +259,7 @@ pub fn generate_rooms(tiles: &mut TileMap) -> Vec<Room> {
     for x in 0..res {
         for y in 0..res {
             match tiles[x][y] {
-                Tile::Room(_) => match Room::generate_room((x, y), tiles, room_counter, 15) {
+                Tile::Room(_) => match Room::generate_room((x, y), tiles, room_counter, min_room_size, adjacency) {
                     Some(room) => {
                         tracing::debug!("found room: {:?}", room);
                         rooms.push(room);
@@ -308,41 +335,16 @@ pub fn generate_rooms(tiles: &mut TileMap) -> Vec<Room> {
 // }
 
 pub fn get_adjacent_coords(coord: &Coord, max_size: usize) -> Vec<Coord> {
-    let mut adjacent_coords = Vec::new();
-
-    // Check above
-    if coord.y > 0 {
-        adjacent_coords.push(Coord {
-            x: coord.x,
-            y: coord.y - 1,
-        });
-    }
-
-    // Check below
-    if coord.y < max_size - 1 {
-        adjacent_coords.push(Coord {
-            x: coord.x,
-            y: coord.y + 1,
-        });
-    }
-
-    // Check left
-    if coord.x > 0 {
-        adjacent_coords.push(Coord {
-            x: coord.x - 1,
-            y: coord.y,
-        });
-    }
-
-    // Check right
-    if coord.x < max_size - 1 {
-        adjacent_coords.push(Coord {
-            x: coord.x + 1,
-            y: coord.y,
-        });
-    }
+    neighbour_coords(coord, Adjacency::Four, max_size)
+}
 
-    adjacent_coords
+/// Neighbours of a coord that lie on a max_size by max_size map
+pub fn neighbour_coords(coord: &Coord, adjacency: Adjacency, max_size: usize) -> Vec<Coord> {
+    adjacency
+        .offsets()
+        .iter()
+        .filter_map(|&step| coord.offset(step, max_size))
+        .collect()
 }
 
 fn dist_squared(a: &Coord, b: &Coord) -> f32 {
@@ -379,8 +381,8 @@ fn dist(a: &Coord, b: &Coord) -> f32 {
 
 pub fn closest_tiles(a: &Room, b: &Room) -> (Coord, Coord) {
 
-    let e1 = &a.edge_tile_indexes;
-    let e2 = &b.edge_tile_indexes;
+    let e1 = a.entry_tile_indexes();
+    let e2 = b.entry_tile_indexes();
 
     let mut dist = f32::MAX;
     let mut s_a: usize = 0;
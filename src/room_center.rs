@@ -0,0 +1,276 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+};
+
+use glam::Vec2;
+
+use crate::{
+    bit_map::distance_transform,
+    types::{CenterStrategy, Coord, FMap},
+};
+
+/// polylabel stops once no cell can beat the best point by this many tiles
+const POLYLABEL_PRECISION: f32 = 0.5;
+
+/// Picks the center tile of a room, always one of `tiles`
+pub fn room_center(tiles: &[Coord], hash: &HashSet<Coord>, strategy: CenterStrategy) -> Coord {
+    if tiles.is_empty() {
+        return Coord::default();
+    }
+
+    let centroid = tiles
+        .iter()
+        .fold(Vec2::ZERO, |acc, t| acc + Vec2::new(t.x as f32, t.y as f32))
+        / tiles.len() as f32;
+
+    match strategy {
+        CenterStrategy::Centroid => nearest_tile(tiles, centroid),
+        CenterStrategy::InscribedCircle => {
+            let field = RoomField::new(tiles);
+            field.deepest(tiles, centroid)
+        }
+        CenterStrategy::MedialAxis => {
+            let field = RoomField::new(tiles);
+            let ridge: Vec<Coord> = tiles.iter().filter(|t| field.is_ridge(t)).copied().collect();
+            if ridge.is_empty() {
+                field.deepest(tiles, centroid)
+            } else {
+                nearest_tile(&ridge, centroid)
+            }
+        }
+        CenterStrategy::Polylabel => {
+            let pole = polylabel(tiles, hash, centroid);
+            let tile = Coord {
+                x: pole.x.max(0.) as usize,
+                y: pole.y.max(0.) as usize,
+            };
+            if hash.contains(&tile) {
+                tile
+            } else {
+                RoomField::new(tiles).deepest(tiles, centroid)
+            }
+        }
+    }
+}
+
+/// the tile whose middle is closest to `point`, the lowest x then y on ties
+/// so the order of the tiles does not matter
+fn nearest_tile(tiles: &[Coord], point: Vec2) -> Coord {
+    let distance = |t: &Coord| Vec2::new(t.x as f32, t.y as f32).distance_squared(point);
+    *tiles
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)).then((a.x, a.y).cmp(&(b.x, b.y))))
+        .expect("tiles is not empty")
+}
+
+/// distance transform of a room over its bounding box
+struct RoomField {
+    min: Coord,
+    distance: FMap,
+}
+
+impl RoomField {
+    fn new(tiles: &[Coord]) -> Self {
+        let min = Coord {
+            x: tiles.iter().map(|t| t.x).min().unwrap_or(0),
+            y: tiles.iter().map(|t| t.y).min().unwrap_or(0),
+        };
+        let w = tiles.iter().map(|t| t.x).max().unwrap_or(0) - min.x + 1;
+        let h = tiles.iter().map(|t| t.y).max().unwrap_or(0) - min.y + 1;
+
+        let mut mask = vec![vec![false; h]; w];
+        for t in tiles {
+            mask[t.x - min.x][t.y - min.y] = true;
+        }
+
+        RoomField {
+            min,
+            distance: distance_transform(&mask),
+        }
+    }
+
+    /// distance to the nearest wall, 0 outside the room
+    fn get(&self, x: isize, y: isize) -> f32 {
+        let (x, y) = (x - self.min.x as isize, y - self.min.y as isize);
+        if x < 0 || y < 0 {
+            return 0.;
+        }
+        self.distance
+            .get(x as usize)
+            .and_then(|column| column.get(y as usize))
+            .copied()
+            .unwrap_or(0.)
+    }
+
+    /// the tile furthest from any wall, the one closest to `centroid` on ties
+    fn deepest(&self, tiles: &[Coord], centroid: Vec2) -> Coord {
+        let deepest = tiles
+            .iter()
+            .map(|t| self.get(t.x as isize, t.y as isize))
+            .fold(0., f32::max);
+        let candidates: Vec<Coord> = tiles
+            .iter()
+            .filter(|t| self.get(t.x as isize, t.y as isize) >= deepest)
+            .copied()
+            .collect();
+        nearest_tile(&candidates, centroid)
+    }
+
+    /// whether the distance peaks at the tile across some direction, rising
+    /// strictly from one side so tiles along a straight wall don't count
+    fn is_ridge(&self, t: &Coord) -> bool {
+        let (x, y) = (t.x as isize, t.y as isize);
+        let d = self.get(x, y);
+        [(1, 0), (0, 1), (1, 1), (1, -1)].iter().any(|&(dx, dy)| {
+            let (a, b) = (self.get(x + dx, y + dy), self.get(x - dx, y - dy));
+            (d > a && d >= b) || (d >= a && d > b)
+        })
+    }
+}
+
+/// a square of the polylabel search, `distance` is signed, positive inside
+struct Cell {
+    center: Vec2,
+    half: f32,
+    distance: f32,
+    /// the best distance any point in the cell could reach
+    potential: f32,
+}
+
+impl Cell {
+    fn new(center: Vec2, half: f32, outline: &Outline) -> Self {
+        let distance = outline.signed_distance(center);
+        Cell {
+            center,
+            half,
+            distance,
+            potential: distance + half * std::f32::consts::SQRT_2,
+        }
+    }
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.potential == other.potential
+    }
+}
+
+impl Eq for Cell {}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.potential.total_cmp(&other.potential)
+    }
+}
+
+/// the tile sides between the room and the rock, with tile (x, y) covering
+/// [x, x + 1] × [y, y + 1]
+struct Outline<'a> {
+    hash: &'a HashSet<Coord>,
+    segments: Vec<(Vec2, Vec2)>,
+}
+
+impl<'a> Outline<'a> {
+    fn new(tiles: &[Coord], hash: &'a HashSet<Coord>) -> Self {
+        let mut segments = vec![];
+        for t in tiles {
+            let (x, y) = (t.x as f32, t.y as f32);
+            let sides = [
+                ((1, 0), Vec2::new(x + 1., y), Vec2::new(x + 1., y + 1.)),
+                ((-1, 0), Vec2::new(x, y), Vec2::new(x, y + 1.)),
+                ((0, 1), Vec2::new(x, y + 1.), Vec2::new(x + 1., y + 1.)),
+                ((0, -1), Vec2::new(x, y), Vec2::new(x + 1., y)),
+            ];
+            for ((dx, dy), a, b) in sides {
+                let (nx, ny) = (t.x as isize + dx, t.y as isize + dy);
+                let inside = nx >= 0
+                    && ny >= 0
+                    && hash.contains(&Coord {
+                        x: nx as usize,
+                        y: ny as usize,
+                    });
+                if !inside {
+                    segments.push((a, b));
+                }
+            }
+        }
+        Outline { hash, segments }
+    }
+
+    fn signed_distance(&self, p: Vec2) -> f32 {
+        let distance = self
+            .segments
+            .iter()
+            .map(|&(a, b)| {
+                let ab = b - a;
+                let t = ((p - a).dot(ab) / ab.length_squared()).clamp(0., 1.);
+                p.distance(a + ab * t)
+            })
+            .fold(f32::INFINITY, f32::min);
+
+        let inside = p.x >= 0.
+            && p.y >= 0.
+            && self.hash.contains(&Coord {
+                x: p.x as usize,
+                y: p.y as usize,
+            });
+        if inside {
+            distance
+        } else {
+            -distance
+        }
+    }
+}
+
+/// Pole of inaccessibility of the room outline, in tile space where tile
+/// centers sit at +0.5
+fn polylabel(tiles: &[Coord], hash: &HashSet<Coord>, centroid: Vec2) -> Vec2 {
+    let outline = Outline::new(tiles, hash);
+    let min = Vec2::new(
+        tiles.iter().map(|t| t.x).min().unwrap_or(0) as f32,
+        tiles.iter().map(|t| t.y).min().unwrap_or(0) as f32,
+    );
+    let max = Vec2::new(
+        tiles.iter().map(|t| t.x).max().unwrap_or(0) as f32 + 1.,
+        tiles.iter().map(|t| t.y).max().unwrap_or(0) as f32 + 1.,
+    );
+    let size = max - min;
+    let half = size.min_element() / 2.;
+
+    let mut queue = BinaryHeap::new();
+    let mut x = min.x;
+    while x < max.x {
+        let mut y = min.y;
+        while y < max.y {
+            queue.push(Cell::new(Vec2::new(x + half, y + half), half, &outline));
+            y += half * 2.;
+        }
+        x += half * 2.;
+    }
+
+    let mut best = centroid + 0.5;
+    let mut best_distance = outline.signed_distance(best);
+    while let Some(cell) = queue.pop() {
+        if cell.distance > best_distance {
+            best = cell.center;
+            best_distance = cell.distance;
+        }
+        if cell.potential - best_distance <= POLYLABEL_PRECISION {
+            continue;
+        }
+
+        let half = cell.half / 2.;
+        for (dx, dy) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+            queue.push(Cell::new(cell.center + Vec2::new(dx, dy) * half, half, &outline));
+        }
+    }
+
+    best
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use uuid::{Builder, Uuid};
+
+use crate::{
+    room::Room,
+    room_center::room_center,
+    types::{CenterStrategy, Coord},
+};
+
+/// bits kept of the resolution and of each anchor coordinate
+const FIELD_BITS: u32 = 30;
+
+/// A room of one generation paired with a room of another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoomMatch {
+    pub old: Uuid,
+    pub new: Uuid,
+    /// shared tiles over the tiles of either room, 1 for identical rooms
+    pub overlap: f32,
+}
+
+/// The stable id of a room, from the planet seed, the map resolution and
+/// the room tile closest to the mean of its tiles.
+///
+/// The anchor does not depend on the center strategy or on the scan order,
+/// so the id only changes when the room itself changes shape.
+pub fn room_uid(seed: u32, resolution: usize, room: &Room) -> Uuid {
+    let anchor = room_center(&room.tiles, &room.tiles_hash, CenterStrategy::Centroid);
+    uid_from_parts(seed, resolution, anchor)
+}
+
+fn uid_from_parts(seed: u32, resolution: usize, anchor: Coord) -> Uuid {
+    let field = |v: usize| v as u128 & ((1 << FIELD_BITS) - 1);
+    let payload = (seed as u128) << (3 * FIELD_BITS)
+        | field(resolution) << (2 * FIELD_BITS)
+        | field(anchor.x) << FIELD_BITS
+        | field(anchor.y);
+
+    // the 122 payload bits go around the 4 version and 2 variant bits
+    let bits = (payload >> 74) << 80 | ((payload >> 62) & 0xfff) << 64 | (payload & ((1 << 62) - 1));
+    Builder::from_custom_bytes(bits.to_be_bytes()).into_uuid()
+}
+
+/// Pairs the rooms of two generations of the same planet at the same
+/// resolution, so state saved against the old rooms can be moved over.
+///
+/// Rooms with the same uid are paired first. The rest are paired greedily by
+/// how much their tiles overlap, best first, as long as the overlap is at
+/// least `min_overlap`. Every room is used at most once, rooms left over
+/// have no counterpart.
+pub fn match_rooms(old: &[Room], new: &[Room], min_overlap: f32) -> Vec<RoomMatch> {
+    let owner: HashMap<Coord, usize> = new
+        .iter()
+        .enumerate()
+        .flat_map(|(j, room)| room.tiles.iter().map(move |t| (*t, j)))
+        .collect();
+
+    let mut candidates: Vec<(usize, usize, f32)> = vec![];
+    for (i, room) in old.iter().enumerate() {
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for t in &room.tiles {
+            if let Some(&j) = owner.get(t) {
+                *shared.entry(j).or_default() += 1;
+            }
+        }
+        for (j, count) in shared {
+            let union = room.tiles.len() + new[j].tiles.len() - count;
+            candidates.push((i, j, count as f32 / union as f32));
+        }
+    }
+
+    let same_uid = |&(i, j, _): &(usize, usize, f32)| old[i].uid == new[j].uid;
+    candidates.sort_by(|a, b| {
+        same_uid(b)
+            .cmp(&same_uid(a))
+            .then(b.2.total_cmp(&a.2))
+            .then((a.0, a.1).cmp(&(b.0, b.1)))
+    });
+
+    let mut old_used = vec![false; old.len()];
+    let mut new_used = vec![false; new.len()];
+    let mut matches = vec![];
+
+    for candidate @ (i, j, overlap) in candidates {
+        if old_used[i] || new_used[j] || (!same_uid(&candidate) && overlap < min_overlap) {
+            continue;
+        }
+        old_used[i] = true;
+        new_used[j] = true;
+        matches.push(RoomMatch {
+            old: old[i].uid,
+            new: new[j].uid,
+            overlap,
+        });
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::{Variant, Version};
+
+    use super::*;
+    use crate::types::Adjacency;
+
+    fn square(x0: usize, y0: usize, size: usize) -> Vec<Coord> {
+        (x0..x0 + size)
+            .flat_map(|x| (y0..y0 + size).map(move |y| Coord { x, y }))
+            .collect()
+    }
+
+    #[test]
+    fn uid_is_a_stable_v8_uuid() {
+        let a = uid_from_parts(42, 512, Coord { x: 10, y: 20 });
+        assert_eq!(a, uid_from_parts(42, 512, Coord { x: 10, y: 20 }));
+        assert_eq!(a.get_version(), Some(Version::Custom));
+        assert_eq!(a.get_variant(), Variant::RFC4122);
+
+        assert_ne!(a, uid_from_parts(43, 512, Coord { x: 10, y: 20 }));
+        assert_ne!(a, uid_from_parts(42, 1024, Coord { x: 10, y: 20 }));
+        assert_ne!(a, uid_from_parts(42, 512, Coord { x: 11, y: 20 }));
+        assert_ne!(a, uid_from_parts(42, 512, Coord { x: 10, y: 21 }));
+        assert_ne!(
+            uid_from_parts(0, 0, Coord { x: 1, y: 0 }),
+            uid_from_parts(0, 0, Coord { x: 0, y: 1 })
+        );
+    }
+
+    #[test]
+    fn uid_ignores_room_id_and_tile_order() {
+        let tiles = square(5, 5, 4);
+        let mut reversed = tiles.clone();
+        reversed.reverse();
+
+        let a = Room::new(tiles, 0, Adjacency::Four);
+        let b = Room::new(reversed, 7, Adjacency::Four);
+        assert_eq!(room_uid(1, 64, &a), room_uid(1, 64, &b));
+    }
+
+    #[test]
+    fn matches_identical_rooms_by_uid() {
+        let rooms = |id_offset| {
+            let mut rooms = vec![
+                Room::new(square(0, 0, 3), id_offset, Adjacency::Four),
+                Room::new(square(10, 10, 3), id_offset + 1, Adjacency::Four),
+            ];
+            for room in &mut rooms {
+                room.uid = room_uid(3, 32, room);
+            }
+            rooms
+        };
+        let (old, new) = (rooms(0), rooms(5));
+
+        let matches = match_rooms(&old, &new, 0.5);
+        assert_eq!(matches.len(), 2);
+        for m in matches {
+            assert_eq!(m.old, m.new);
+            assert_eq!(m.overlap, 1.);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use glam::Vec2;
+
+use crate::{
+    room::Room,
+    types::{Coord, RoomId},
+};
+
+/// side of a bucket of the index, in tiles
+const CELL_SIZE: usize = 16;
+
+/// Point and area queries over the rooms of a planet.
+///
+/// Grid positions are in tiles, tile (x, y) spanning [x, x + 1] × [y, y + 1].
+/// World positions are the normalized coordinates of
+/// `Coord::into_world_normalized_vec2`, running from -1 to 1 with y up.
+/// Distances are measured to the middle of the closest tile of a room, and
+/// are 0 for a point inside the room.
+#[derive(Clone, Debug, Default)]
+pub struct RoomIndex {
+    resolution: usize,
+    /// the room of every tile, indexed [x][y]
+    lookup: Vec<Vec<Option<RoomId>>>,
+    /// tiles on the boundary of a room, per bucket
+    boundary: Vec<Vec<Vec<(Coord, RoomId)>>>,
+    /// rooms with any tile in the bucket
+    buckets: Vec<Vec<Vec<RoomId>>>,
+}
+
+impl RoomIndex {
+    pub fn new(rooms: &[Room], resolution: usize) -> Self {
+        let cells = resolution.div_ceil(CELL_SIZE);
+        let mut index = RoomIndex {
+            resolution,
+            lookup: vec![vec![None; resolution]; resolution],
+            boundary: vec![vec![vec![]; cells]; cells],
+            buckets: vec![vec![vec![]; cells]; cells],
+        };
+
+        for room in rooms {
+            for t in &room.tiles {
+                index.lookup[t.x][t.y] = Some(room.id);
+
+                let bucket = &mut index.buckets[t.x / CELL_SIZE][t.y / CELL_SIZE];
+                if !bucket.contains(&room.id) {
+                    bucket.push(room.id);
+                }
+
+                let on_boundary = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .into_iter()
+                    .any(|step| t.offset(step, usize::MAX).is_none_or(|n| !room.tiles_hash.contains(&n)));
+                if on_boundary {
+                    index.boundary[t.x / CELL_SIZE][t.y / CELL_SIZE].push((*t, room.id));
+                }
+            }
+        }
+
+        index
+    }
+
+    /// The room covering a grid position
+    pub fn room_at(&self, p: Vec2) -> Option<RoomId> {
+        let t = self.tile_at(p)?;
+        self.lookup[t.x][t.y]
+    }
+
+    /// The closest room to a grid position and its distance in tiles
+    pub fn nearest_room(&self, p: Vec2) -> Option<(RoomId, f32)> {
+        if let Some(id) = self.room_at(p) {
+            return Some((id, 0.));
+        }
+
+        let cells = self.boundary.len();
+        let (cx, cy) = self.cell_at(p)?;
+        let mut best: Option<(RoomId, f32)> = None;
+
+        for ring in 0..cells {
+            for (x, y) in ring_cells(cx, cy, ring, cells) {
+                for &(t, id) in &self.boundary[x][y] {
+                    let d = distance_to_tile(p, t);
+                    if best.is_none_or(|(best_id, best_d)| (d, id) < (best_d, best_id)) {
+                        best = Some((id, d));
+                    }
+                }
+            }
+            // every bucket further out is at least this far away
+            if best.is_some_and(|(_, d)| d <= (ring * CELL_SIZE) as f32) {
+                break;
+            }
+        }
+
+        best
+    }
+
+    /// Rooms within `radius` tiles of a grid position, closest first
+    pub fn rooms_within(&self, p: Vec2, radius: f32) -> Vec<(RoomId, f32)> {
+        let mut found: BTreeMap<RoomId, f32> = BTreeMap::new();
+        if let Some(id) = self.room_at(p) {
+            found.insert(id, 0.);
+        }
+
+        let (min, max) = (p - Vec2::splat(radius), p + Vec2::splat(radius));
+        if let Some(((x0, y0), (x1, y1))) = self.cell_range(min, max) {
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    for &(t, id) in &self.boundary[x][y] {
+                        let d = distance_to_tile(p, t);
+                        if d <= radius {
+                            let entry = found.entry(id).or_insert(d);
+                            *entry = entry.min(d);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut rooms: Vec<(RoomId, f32)> = found.into_iter().collect();
+        rooms.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        rooms
+    }
+
+    /// Rooms with any tile overlapping the grid rectangle from `min` to `max`, by id
+    pub fn rooms_in_rect(&self, min: Vec2, max: Vec2) -> Vec<RoomId> {
+        let Some(((x0, y0), (x1, y1))) = self.tile_range(min, max) else {
+            return vec![];
+        };
+
+        let mut found = BTreeSet::new();
+        for cx in x0 / CELL_SIZE..=x1 / CELL_SIZE {
+            for cy in y0 / CELL_SIZE..=y1 / CELL_SIZE {
+                let (tx0, ty0) = (cx * CELL_SIZE, cy * CELL_SIZE);
+                let (tx1, ty1) = (tx0 + CELL_SIZE - 1, ty0 + CELL_SIZE - 1);
+
+                if tx0 >= x0 && ty0 >= y0 && tx1 <= x1 && ty1 <= y1 {
+                    found.extend(self.buckets[cx][cy].iter().copied());
+                    continue;
+                }
+                for x in tx0.max(x0)..=tx1.min(x1) {
+                    for y in ty0.max(y0)..=ty1.min(y1) {
+                        found.extend(self.lookup[x][y]);
+                    }
+                }
+            }
+        }
+
+        found.into_iter().collect()
+    }
+
+    /// Converts a world position to a grid position
+    pub fn world_to_grid(&self, world: Vec2) -> Vec2 {
+        let r = self.resolution as f32;
+        Vec2::new((world.x + 1.) / 2. * r, (1. - world.y) / 2. * r)
+    }
+
+    pub fn room_at_world(&self, world: Vec2) -> Option<RoomId> {
+        self.room_at(self.world_to_grid(world))
+    }
+
+    /// The closest room to a world position and its distance in world units
+    pub fn nearest_room_world(&self, world: Vec2) -> Option<(RoomId, f32)> {
+        self.nearest_room(self.world_to_grid(world))
+            .map(|(id, d)| (id, d / self.world_scale()))
+    }
+
+    /// Rooms within `radius` world units of a world position, closest first
+    pub fn rooms_within_world(&self, world: Vec2, radius: f32) -> Vec<(RoomId, f32)> {
+        let scale = self.world_scale();
+        self.rooms_within(self.world_to_grid(world), radius * scale)
+            .into_iter()
+            .map(|(id, d)| (id, d / scale))
+            .collect()
+    }
+
+    /// Rooms overlapping the world rectangle between two corners, by id
+    pub fn rooms_in_rect_world(&self, a: Vec2, b: Vec2) -> Vec<RoomId> {
+        let (a, b) = (self.world_to_grid(a), self.world_to_grid(b));
+        self.rooms_in_rect(a.min(b), a.max(b))
+    }
+
+    /// tiles per world unit
+    fn world_scale(&self) -> f32 {
+        self.resolution as f32 / 2.
+    }
+
+    fn tile_at(&self, p: Vec2) -> Option<Coord> {
+        let r = self.resolution as f32;
+        (p.x >= 0. && p.y >= 0. && p.x < r && p.y < r).then_some(Coord {
+            x: p.x as usize,
+            y: p.y as usize,
+        })
+    }
+
+    /// the bucket holding a position, clamped to the map
+    fn cell_at(&self, p: Vec2) -> Option<(usize, usize)> {
+        let last = self.boundary.len().checked_sub(1)?;
+        let cell = |v: f32| ((v.max(0.) as usize) / CELL_SIZE).min(last);
+        Some((cell(p.x), cell(p.y)))
+    }
+
+    /// inclusive tile range of a rectangle, clipped to the map
+    fn tile_range(&self, min: Vec2, max: Vec2) -> Option<((usize, usize), (usize, usize))> {
+        let r = self.resolution as f32;
+        if max.x < 0. || max.y < 0. || min.x >= r || min.y >= r || min.x > max.x || min.y > max.y {
+            return None;
+        }
+        let tile = |v: f32| (v.max(0.) as usize).min(self.resolution - 1);
+        Some(((tile(min.x), tile(min.y)), (tile(max.x), tile(max.y))))
+    }
+
+    /// inclusive bucket range of a rectangle, clipped to the map
+    fn cell_range(&self, min: Vec2, max: Vec2) -> Option<((usize, usize), (usize, usize))> {
+        let ((x0, y0), (x1, y1)) = self.tile_range(min, max)?;
+        Some(((x0 / CELL_SIZE, y0 / CELL_SIZE), (x1 / CELL_SIZE, y1 / CELL_SIZE)))
+    }
+}
+
+fn distance_to_tile(p: Vec2, t: Coord) -> f32 {
+    p.distance(Vec2::new(t.x as f32 + 0.5, t.y as f32 + 0.5))
+}
+
+/// buckets at chebyshev distance `ring` from (cx, cy), within the map
+fn ring_cells(cx: usize, cy: usize, ring: usize, cells: usize) -> impl Iterator<Item = (usize, usize)> {
+    let (cx, cy, ring) = (cx as isize, cy as isize, ring as isize);
+    (cx - ring..=cx + ring)
+        .flat_map(move |x| (cy - ring..=cy + ring).map(move |y| (x, y)))
+        .filter(move |&(x, y)| (x - cx).abs().max((y - cy).abs()) == ring)
+        .filter(move |&(x, y)| x >= 0 && y >= 0 && x < cells as isize && y < cells as isize)
+        .map(|(x, y)| (x as usize, y as usize))
+}
@@ -0,0 +1,149 @@
+use glam::Vec2;
+
+use crate::{
+    bit_map::distance_transform,
+    room::Room,
+    types::{Coord, FMap},
+};
+
+/// Geometry of a room, and where it sits in the planet
+#[derive(Clone, Debug, Default)]
+pub struct RoomMetrics {
+    /// number of tiles
+    pub area: usize,
+    /// number of tile sides facing a tile outside the room
+    pub perimeter: usize,
+    /// inclusive min and max corners
+    pub bbox: (Coord, Coord),
+    pub centroid: Vec2,
+    /// radius of the largest circle that fits inside the room, in tiles
+    pub inscribed_radius: f32,
+    /// 4π area / perimeter², 1 for a disc and towards 0 for thin shapes
+    pub compactness: f32,
+    /// angle of the long axis from the x axis, in radians
+    pub principal_axis: f32,
+    /// ratio of the spread along the long axis to the spread across it
+    pub elongation: f32,
+    pub mean_depth: f32,
+    pub mean_altitude: f32,
+    /// tiles with rock directly below them, towards the centre of the planet
+    pub floor_tiles: usize,
+    /// tiles with rock directly above them, away from the centre of the planet
+    pub ceiling_tiles: usize,
+}
+
+impl RoomMetrics {
+    /// Measures a room, with gravity pulling towards the centre of the fields
+    pub fn measure(room: &Room, depth: &FMap, altitude: &FMap) -> Self {
+        let area = room.tiles.len();
+        if area == 0 {
+            return Self::default();
+        }
+
+        let bbox = room.get_min_max_coords();
+        let n = area as f32;
+
+        let sum = room
+            .tiles
+            .iter()
+            .fold(Vec2::ZERO, |acc, t| acc + Vec2::new(t.x as f32, t.y as f32));
+        let centroid = sum / n;
+
+        // second central moments give the orientation and spread of the tiles
+        let (mut mu20, mut mu02, mut mu11) = (0., 0., 0.);
+        for t in &room.tiles {
+            let d = Vec2::new(t.x as f32, t.y as f32) - centroid;
+            mu20 += d.x * d.x;
+            mu02 += d.y * d.y;
+            mu11 += d.x * d.y;
+        }
+        let (mu20, mu02, mu11) = (mu20 / n, mu02 / n, mu11 / n);
+        let principal_axis = 0.5 * (2. * mu11).atan2(mu20 - mu02);
+        let spread = ((mu20 - mu02).powi(2) + 4. * mu11 * mu11).sqrt();
+        let major = (mu20 + mu02 + spread) / 2.;
+        let minor = (mu20 + mu02 - spread) / 2.;
+        let elongation = if minor > f32::EPSILON {
+            (major / minor).sqrt()
+        } else if major > f32::EPSILON {
+            f32::INFINITY
+        } else {
+            1.
+        };
+
+        let perimeter = room
+            .tiles
+            .iter()
+            .map(|t| {
+                [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .iter()
+                    .filter(|&&step| !contains_step(room, t, step))
+                    .count()
+            })
+            .sum();
+        let compactness = if perimeter > 0 {
+            4. * std::f32::consts::PI * n / (perimeter * perimeter) as f32
+        } else {
+            0.
+        };
+
+        let (min, max) = bbox;
+        let mask: Vec<Vec<bool>> = (min.x..=max.x)
+            .map(|x| {
+                (min.y..=max.y)
+                    .map(|y| room.tiles_hash.contains(&Coord { x, y }))
+                    .collect()
+            })
+            .collect();
+        let inscribed_radius = distance_transform(&mask)
+            .iter()
+            .flatten()
+            .fold(0., |a: f32, &b| a.max(b));
+
+        let mean_depth = room.tiles.iter().map(|t| depth[t.x][t.y]).sum::<f32>() / n;
+        let mean_altitude = room.tiles.iter().map(|t| altitude[t.x][t.y]).sum::<f32>() / n;
+
+        let center = depth.len() as f32 / 2.;
+        let (mut floor_tiles, mut ceiling_tiles) = (0, 0);
+        for t in &room.tiles {
+            let down = Vec2::new(center - t.x as f32, center - t.y as f32);
+            if down.length_squared() < 1. {
+                continue;
+            }
+            let down = down.normalize();
+            let step = (down.x.round() as i32, down.y.round() as i32);
+            if !contains_step(room, t, step) {
+                floor_tiles += 1;
+            }
+            if !contains_step(room, t, (-step.0, -step.1)) {
+                ceiling_tiles += 1;
+            }
+        }
+
+        RoomMetrics {
+            area,
+            perimeter,
+            bbox,
+            centroid,
+            inscribed_radius,
+            compactness,
+            principal_axis,
+            elongation,
+            mean_depth,
+            mean_altitude,
+            floor_tiles,
+            ceiling_tiles,
+        }
+    }
+}
+
+/// whether the tile one step away is part of the room
+fn contains_step(room: &Room, t: &Coord, step: (i32, i32)) -> bool {
+    let x = t.x as i32 + step.0;
+    let y = t.y as i32 + step.1;
+    x >= 0
+        && y >= 0
+        && room.tiles_hash.contains(&Coord {
+            x: x as usize,
+            y: y as usize,
+        })
+}
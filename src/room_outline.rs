@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::Vec2;
+
+use crate::types::{Adjacency, Coord, PolyLine};
+
+/// A closed boundary of a room with the rock pillars inside it.
+///
+/// Points are tile corners, tile (x, y) spanning [x, x + 1] × [y, y + 1], and
+/// the last point connects back to the first. In grid coordinates `outer`
+/// has a positive and every hole a negative shoelace area.
+#[derive(Clone, Debug, Default)]
+pub struct RoomOutline {
+    pub outer: PolyLine,
+    pub holes: Vec<PolyLine>,
+}
+
+impl RoomOutline {
+    /// The outline in the normalized world coordinates of
+    /// `Coord::into_world_normalized_vec2`, where y points up and the
+    /// winding of every ring flips
+    pub fn to_world(&self, resolution: u32) -> RoomOutline {
+        let r = resolution as f32;
+        let world = |ring: &PolyLine| -> PolyLine {
+            ring.iter()
+                .map(|p| Vec2::new(p.x / r * 2. - 1., -(p.y / r * 2. - 1.)))
+                .collect()
+        };
+        RoomOutline {
+            outer: world(&self.outer),
+            holes: self.holes.iter().map(world).collect(),
+        }
+    }
+}
+
+type Corner = (usize, usize);
+
+/// Traces the boundary of a set of tiles into closed rings, one outline per
+/// outer ring with the holes inside it.
+///
+/// Where two tiles only touch at a corner the rings are kept apart with
+/// `Adjacency::Four` and joined into one ring with `Adjacency::Eight`,
+/// matching how the room was filled.
+pub fn trace_outlines(tiles: &[Coord], hash: &HashSet<Coord>, adjacency: Adjacency) -> Vec<RoomOutline> {
+    let inside = |x: usize, y: usize, dx: isize, dy: isize| {
+        match (x.checked_add_signed(dx), y.checked_add_signed(dy)) {
+            (Some(x), Some(y)) => hash.contains(&Coord { x, y }),
+            _ => false,
+        }
+    };
+
+    // every tile side facing out of the room, directed so outer rings wind positively
+    let mut edges: Vec<(Corner, Corner)> = vec![];
+    for t in tiles {
+        let (x, y) = (t.x, t.y);
+        if !inside(x, y, 0, -1) {
+            edges.push(((x, y), (x + 1, y)));
+        }
+        if !inside(x, y, 1, 0) {
+            edges.push(((x + 1, y), (x + 1, y + 1)));
+        }
+        if !inside(x, y, 0, 1) {
+            edges.push(((x + 1, y + 1), (x, y + 1)));
+        }
+        if !inside(x, y, -1, 0) {
+            edges.push(((x, y + 1), (x, y)));
+        }
+    }
+
+    let mut outgoing: HashMap<Corner, Vec<usize>> = HashMap::new();
+    for (i, (from, _)) in edges.iter().enumerate() {
+        outgoing.entry(*from).or_default().push(i);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut rings: Vec<PolyLine> = vec![];
+
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+
+        let mut ring = vec![];
+        let mut current = first;
+        loop {
+            used[current] = true;
+            let (from, to) = edges[current];
+            ring.push(corner_vec2(from));
+
+            let heading = direction(edges[current]);
+            let next = outgoing[&to]
+                .iter()
+                .copied()
+                .filter(|&e| !used[e] || e == first)
+                .min_by_key(|&e| turn_rank(heading, direction(edges[e]), adjacency));
+            match next {
+                Some(e) if e != first => current = e,
+                _ => break,
+            }
+        }
+        rings.push(simplify(ring));
+    }
+
+    let (outers, holes): (Vec<PolyLine>, Vec<PolyLine>) =
+        rings.into_iter().partition(|ring| signed_area(ring) > 0.);
+
+    let mut outlines: Vec<RoomOutline> = outers
+        .into_iter()
+        .map(|outer| RoomOutline { outer, holes: vec![] })
+        .collect();
+
+    for hole in holes {
+        // a point just inside the pillar, to the right of the first side
+        let side = hole[1] - hole[0];
+        let probe = (hole[0] + hole[1]) / 2. + Vec2::new(side.y, -side.x).normalize_or_zero() * 0.25;
+        let owner = outlines
+            .iter()
+            .position(|outline| contains(&outline.outer, probe))
+            .unwrap_or(0);
+        if let Some(outline) = outlines.get_mut(owner) {
+            outline.holes.push(hole);
+        }
+    }
+
+    outlines
+}
+
+fn corner_vec2(c: Corner) -> Vec2 {
+    Vec2::new(c.0 as f32, c.1 as f32)
+}
+
+fn direction((from, to): (Corner, Corner)) -> (isize, isize) {
+    (to.0 as isize - from.0 as isize, to.1 as isize - from.1 as isize)
+}
+
+/// Where two tiles meet at a corner, turning left keeps them apart and
+/// turning right joins them into one ring
+fn turn_rank(heading: (isize, isize), next: (isize, isize), adjacency: Adjacency) -> u8 {
+    let first = match adjacency {
+        Adjacency::Four => (-heading.1, heading.0),
+        Adjacency::Eight => (heading.1, -heading.0),
+    };
+    if next == first {
+        0
+    } else if next == heading {
+        1
+    } else {
+        2
+    }
+}
+
+/// drops the corners in the middle of straight runs
+fn simplify(ring: PolyLine) -> PolyLine {
+    let n = ring.len();
+    (0..n)
+        .filter(|&i| {
+            let prev = ring[(i + n - 1) % n];
+            let next = ring[(i + 1) % n];
+            (ring[i] - prev).perp_dot(next - ring[i]) != 0.
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+fn signed_area(ring: &PolyLine) -> f32 {
+    let n = ring.len();
+    (0..n).map(|i| ring[i].perp_dot(ring[(i + 1) % n])).sum::<f32>() / 2.
+}
+
+/// even-odd point in polygon test
+fn contains(ring: &PolyLine, p: Vec2) -> bool {
+    let n = ring.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (a, b) = (ring[i], ring[(i + 1) % n]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outlines(tiles: &[(usize, usize)], adjacency: Adjacency) -> Vec<RoomOutline> {
+        let tiles: Vec<Coord> = tiles.iter().map(|&(x, y)| Coord { x, y }).collect();
+        let hash: HashSet<Coord> = tiles.iter().copied().collect();
+        trace_outlines(&tiles, &hash, adjacency)
+    }
+
+    #[test]
+    fn square_is_one_ring_of_four_corners() {
+        let tiles: Vec<(usize, usize)> = (2..5).flat_map(|x| (3..5).map(move |y| (x, y))).collect();
+        let result = outlines(&tiles, Adjacency::Four);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].outer.len(), 4);
+        assert_eq!(signed_area(&result[0].outer), 6.);
+        assert!(result[0].holes.is_empty());
+    }
+
+    #[test]
+    fn ring_keeps_its_hole() {
+        let tiles: Vec<(usize, usize)> = (0..4)
+            .flat_map(|x| (0..4).map(move |y| (x, y)))
+            .filter(|&(x, y)| !(1..3).contains(&x) || !(1..3).contains(&y))
+            .collect();
+
+        for adjacency in [Adjacency::Four, Adjacency::Eight] {
+            let result = outlines(&tiles, adjacency);
+            assert_eq!(result.len(), 1);
+            assert_eq!(signed_area(&result[0].outer), 16.);
+            assert_eq!(result[0].holes.len(), 1);
+            assert_eq!(signed_area(&result[0].holes[0]), -4.);
+
+            let world = result[0].to_world(8);
+            assert!(signed_area(&world.outer) < 0.);
+            assert!(signed_area(&world.holes[0]) > 0.);
+        }
+    }
+
+    #[test]
+    fn diagonal_tiles_follow_the_adjacency() {
+        let tiles = [(1, 1), (2, 2), (3, 1)];
+
+        let four = outlines(&tiles, Adjacency::Four);
+        assert_eq!(four.len(), 3);
+        assert!(four.iter().all(|o| signed_area(&o.outer) == 1. && o.holes.is_empty()));
+
+        let eight = outlines(&tiles, Adjacency::Eight);
+        assert_eq!(eight.len(), 1);
+        assert_eq!(signed_area(&eight[0].outer), 3.);
+        assert!(eight[0].holes.is_empty());
+    }
+
+    #[test]
+    fn pillar_touching_the_wall_diagonally() {
+        // a 3x3 room missing its middle and one corner, so the pillar meets the rock at a corner
+        let tiles: Vec<(usize, usize)> = (0..3)
+            .flat_map(|x| (0..3).map(move |y| (x, y)))
+            .filter(|&t| t != (1, 1) && t != (2, 2))
+            .collect();
+
+        let four = outlines(&tiles, Adjacency::Four);
+        let area: f32 = four.iter().map(|o| signed_area(&o.outer)).sum::<f32>()
+            + four.iter().flat_map(|o| &o.holes).map(signed_area).sum::<f32>();
+        assert_eq!(area, 7.);
+
+        let eight = outlines(&tiles, Adjacency::Eight);
+        assert_eq!(eight.len(), 1);
+        let area = signed_area(&eight[0].outer) + eight[0].holes.iter().map(signed_area).sum::<f32>();
+        assert_eq!(area, 7.);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    bit_map::distance_transform,
+    room::neighbour_coords,
+    types::{Adjacency, Coord},
+};
+
+/// Splits the tiles of an oversized room into pieces of at most `max_size`
+/// tiles, cutting at chokepoints.
+///
+/// Each split erodes the room by its distance transform until it falls apart
+/// into several cores, then grows the cores back over the room so every tile
+/// joins the core it reaches first. Pieces smaller than `min_size` are merged
+/// into the neighbour they touch most. A room with no chokepoint is cut in
+/// half across its long axis instead.
+pub fn split_room(tiles: &[Coord], max_size: usize, min_size: usize, adjacency: Adjacency) -> Vec<Vec<Coord>> {
+    if tiles.len() <= max_size.max(1) {
+        return vec![tiles.to_vec()];
+    }
+
+    let pieces = split_at_chokepoint(tiles, min_size, adjacency)
+        .unwrap_or_else(|| split_across_axis(tiles, min_size, adjacency));
+    if pieces.len() < 2 {
+        return vec![tiles.to_vec()];
+    }
+
+    pieces
+        .iter()
+        .flat_map(|piece| split_room(piece, max_size, min_size, adjacency))
+        .collect()
+}
+
+/// Segments connected open space into chambers with a watershed over its
+/// distance transform.
+///
+/// Tiles are flooded from the widest point down. Where two basins meet, the
+/// shallower one is merged in unless the meeting point is narrower than
+/// `saddle_ratio` times its own widest point, so chambers joined by a thin
+/// gap stay apart. Pieces smaller than `min_size` are merged into the
+/// neighbour they touch most.
+pub fn watershed(tiles: &[Coord], saddle_ratio: f32, min_size: usize, adjacency: Adjacency) -> Vec<Vec<Coord>> {
+    let (Some(min_x), Some(min_y)) = (tiles.iter().map(|t| t.x).min(), tiles.iter().map(|t| t.y).min()) else {
+        return vec![];
+    };
+    let w = tiles.iter().map(|t| t.x).max().unwrap_or(min_x) - min_x + 1;
+    let h = tiles.iter().map(|t| t.y).max().unwrap_or(min_y) - min_y + 1;
+
+    let mut mask = vec![vec![false; h]; w];
+    for t in tiles {
+        mask[t.x - min_x][t.y - min_y] = true;
+    }
+    let distance = distance_transform(&mask);
+    let depth = |t: &Coord| distance[t.x - min_x][t.y - min_y];
+
+    let mut order: Vec<usize> = (0..tiles.len()).collect();
+    order.sort_by(|&a, &b| depth(&tiles[b]).total_cmp(&depth(&tiles[a])));
+
+    let mut label: HashMap<Coord, usize> = HashMap::new();
+    let mut parent: Vec<usize> = vec![];
+    let mut peak: Vec<f32> = vec![];
+
+    for i in order {
+        let t = tiles[i];
+        let d = depth(&t);
+
+        let mut basins: Vec<usize> = neighbours(&t, adjacency)
+            .filter_map(|n| label.get(&n).map(|&b| find(&mut parent, b)))
+            .collect();
+        basins.sort_by(|&a, &b| peak[b].total_cmp(&peak[a]).then(a.cmp(&b)));
+        basins.dedup();
+
+        let Some(&highest) = basins.first() else {
+            label.insert(t, parent.len());
+            parent.push(parent.len());
+            peak.push(d);
+            continue;
+        };
+
+        label.insert(t, highest);
+        for &b in &basins[1..] {
+            if d >= saddle_ratio * peak[b] {
+                parent[b] = highest;
+            }
+        }
+    }
+
+    let mut pieces: Vec<Vec<Coord>> = vec![vec![]; parent.len()];
+    for t in tiles {
+        let b = find(&mut parent, label[t]);
+        pieces[b].push(*t);
+    }
+    pieces.retain(|piece| !piece.is_empty());
+
+    merge_small(pieces, min_size, adjacency)
+}
+
+fn find(parent: &mut [usize], mut b: usize) -> usize {
+    while parent[b] != b {
+        parent[b] = parent[parent[b]];
+        b = parent[b];
+    }
+    b
+}
+
+fn split_at_chokepoint(tiles: &[Coord], min_size: usize, adjacency: Adjacency) -> Option<Vec<Vec<Coord>>> {
+    let min = Coord {
+        x: tiles.iter().map(|t| t.x).min()?,
+        y: tiles.iter().map(|t| t.y).min()?,
+    };
+    let w = tiles.iter().map(|t| t.x).max()? - min.x + 1;
+    let h = tiles.iter().map(|t| t.y).max()? - min.y + 1;
+
+    let mut mask = vec![vec![false; h]; w];
+    for t in tiles {
+        mask[t.x - min.x][t.y - min.y] = true;
+    }
+    let distance = distance_transform(&mask);
+    let deepest = distance.iter().flatten().fold(0., |a: f32, &b| a.max(b));
+    let all: HashSet<Coord> = tiles.iter().copied().collect();
+
+    let mut level = 1.;
+    while level < deepest {
+        let inner: Vec<Coord> = tiles
+            .iter()
+            .filter(|t| distance[t.x - min.x][t.y - min.y] > level)
+            .copied()
+            .collect();
+        let cores = components(&inner, adjacency);
+
+        if cores.len() >= 2 {
+            let pieces = merge_small(grow(tiles, &all, cores, adjacency), min_size, adjacency);
+            if pieces.len() >= 2 {
+                return Some(pieces);
+            }
+        }
+        level += 1.;
+    }
+
+    None
+}
+
+/// cuts at the median across the axis the tiles spread most along
+fn split_across_axis(tiles: &[Coord], min_size: usize, adjacency: Adjacency) -> Vec<Vec<Coord>> {
+    let n = tiles.len() as f32;
+    let (cx, cy) = tiles
+        .iter()
+        .fold((0., 0.), |acc, t| (acc.0 + t.x as f32 / n, acc.1 + t.y as f32 / n));
+    let (mut mu20, mut mu02, mut mu11) = (0., 0., 0.);
+    for t in tiles {
+        let (dx, dy) = (t.x as f32 - cx, t.y as f32 - cy);
+        mu20 += dx * dx;
+        mu02 += dy * dy;
+        mu11 += dx * dy;
+    }
+    let axis = 0.5 * (2. * mu11).atan2(mu20 - mu02);
+    let project = |t: &Coord| (t.x as f32 - cx) * axis.cos() + (t.y as f32 - cy) * axis.sin();
+
+    let mut projections: Vec<f32> = tiles.iter().map(project).collect();
+    projections.sort_by(f32::total_cmp);
+    let median = projections[projections.len() / 2];
+
+    let (a, b): (Vec<Coord>, Vec<Coord>) = tiles.iter().partition(|t| project(t) < median);
+    let mut pieces = components(&a, adjacency);
+    pieces.extend(components(&b, adjacency));
+    merge_small(pieces, min_size, adjacency)
+}
+
+/// connected components of the tiles, in the order they are first met
+fn components(tiles: &[Coord], adjacency: Adjacency) -> Vec<Vec<Coord>> {
+    let set: HashSet<Coord> = tiles.iter().copied().collect();
+    let mut seen: HashSet<Coord> = HashSet::new();
+    let mut out = vec![];
+
+    for &start in tiles {
+        if !seen.insert(start) {
+            continue;
+        }
+        let mut component = vec![];
+        let mut queue = VecDeque::from([start]);
+        while let Some(c) = queue.pop_front() {
+            component.push(c);
+            for n in neighbours(&c, adjacency) {
+                if set.contains(&n) && seen.insert(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+        out.push(component);
+    }
+
+    out
+}
+
+/// grows the cores over every tile breadth first, one piece per core
+fn grow(tiles: &[Coord], set: &HashSet<Coord>, cores: Vec<Vec<Coord>>, adjacency: Adjacency) -> Vec<Vec<Coord>> {
+    let mut label: HashMap<Coord, usize> = HashMap::new();
+    let mut queue = VecDeque::new();
+    let count = cores.len();
+
+    for (i, core) in cores.into_iter().enumerate() {
+        for c in core {
+            label.insert(c, i);
+            queue.push_back(c);
+        }
+    }
+
+    while let Some(c) = queue.pop_front() {
+        let l = label[&c];
+        for n in neighbours(&c, adjacency) {
+            if set.contains(&n) && !label.contains_key(&n) {
+                label.insert(n, l);
+                queue.push_back(n);
+            }
+        }
+    }
+
+    let mut pieces = vec![vec![]; count];
+    for c in tiles {
+        if let Some(&l) = label.get(c) {
+            pieces[l].push(*c);
+        }
+    }
+    pieces
+}
+
+/// merges pieces below `min_size` into the neighbouring piece touching it most
+fn merge_small(mut pieces: Vec<Vec<Coord>>, min_size: usize, adjacency: Adjacency) -> Vec<Vec<Coord>> {
+    loop {
+        let label: HashMap<Coord, usize> = pieces
+            .iter()
+            .enumerate()
+            .flat_map(|(i, piece)| piece.iter().map(move |c| (*c, i)))
+            .collect();
+
+        // smallest piece first, skipping any with no neighbour to merge into
+        let mut order: Vec<usize> = (0..pieces.len())
+            .filter(|&i| pieces[i].len() < min_size)
+            .collect();
+        order.sort_by_key(|&i| pieces[i].len());
+
+        let merge = order.into_iter().find_map(|i| {
+            let mut contacts: HashMap<usize, usize> = HashMap::new();
+            for c in &pieces[i] {
+                for n in neighbours(c, adjacency) {
+                    match label.get(&n) {
+                        Some(&j) if j != i => *contacts.entry(j).or_default() += 1,
+                        _ => {}
+                    }
+                }
+            }
+            contacts
+                .into_iter()
+                .max_by_key(|&(j, count)| (count, std::cmp::Reverse(j)))
+                .map(|(j, _)| (i, j))
+        });
+
+        match merge {
+            Some((i, j)) => {
+                let piece = std::mem::take(&mut pieces[i]);
+                pieces[j].extend(piece);
+                pieces.swap_remove(i);
+            }
+            None => return pieces,
+        }
+    }
+}
+
+fn neighbours(c: &Coord, adjacency: Adjacency) -> impl Iterator<Item = Coord> {
+    neighbour_coords(c, adjacency, usize::MAX).into_iter()
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use crate::{
+    roooms::Roooms,
+    types::{Coord, RoomTag, RoomTagOptions, TagOrder, TagRule},
+};
+
+/// Tags the rooms by running every rule in order.
+///
+/// Degree and distance follow the room graph, so loops added to it count.
+/// The room nearest to `entrance` is at distance 0, or the shallowest room
+/// when there is no entrance. Rooms the graph does not reach have no distance
+/// and never match a distance condition. Area and depth come from the room
+/// metrics, so rooms should be measured first.
+pub fn tag_rooms(roooms: &mut Roooms, options: &RoomTagOptions, entrance: Option<Coord>) {
+    let n = roooms.rooms.len();
+    if n == 0 {
+        return;
+    }
+
+    let mut adjacency = vec![vec![]; n];
+    for &(a, b) in roooms.graph.iter().flatten() {
+        adjacency[a].push(b);
+        adjacency[b].push(a);
+    }
+
+    let area = |i: usize| {
+        let room = &roooms.rooms[i];
+        room.metrics.as_ref().map_or(room.tiles.len(), |m| m.area)
+    };
+    let depth = |i: usize| roooms.rooms[i].metrics.as_ref().map_or(0., |m| m.mean_depth);
+
+    let start = match entrance {
+        Some(e) => (0..n).min_by_key(|&i| {
+            roooms.rooms[i]
+                .tiles
+                .iter()
+                .map(|t| {
+                    let (dx, dy) = (t.x.abs_diff(e.x), t.y.abs_diff(e.y));
+                    dx * dx + dy * dy
+                })
+                .min()
+                .unwrap_or(usize::MAX)
+        }),
+        None => (0..n).max_by(|&a, &b| depth(a).total_cmp(&depth(b))),
+    }
+    .expect("there is at least one room");
+    let distance = graph_distances(&adjacency, start);
+
+    let mut tags: Vec<Vec<RoomTag>> = roooms.rooms.iter().map(|room| room.tags.clone()).collect();
+
+    for rule in &options.rules {
+        let mut matches: Vec<usize> = (0..n)
+            .filter(|&i| {
+                matches_rule(
+                    rule,
+                    &tags[i],
+                    area(i),
+                    depth(i),
+                    adjacency[i].len(),
+                    distance[i],
+                )
+            })
+            .collect();
+
+        match rule.order {
+            TagOrder::Any => {}
+            TagOrder::Nearest => matches.sort_by_key(|&i| distance[i].unwrap_or(usize::MAX)),
+            TagOrder::Farthest => matches.sort_by_key(|&i| std::cmp::Reverse(distance[i])),
+            TagOrder::Smallest => matches.sort_by_key(|&i| area(i)),
+            TagOrder::Largest => matches.sort_by_key(|&i| std::cmp::Reverse(area(i))),
+            TagOrder::Shallowest => matches.sort_by(|&a, &b| depth(b).total_cmp(&depth(a))),
+            TagOrder::Deepest => matches.sort_by(|&a, &b| depth(a).total_cmp(&depth(b))),
+        }
+
+        for &i in matches.iter().take(rule.quota.unwrap_or(usize::MAX)) {
+            tags[i].push(rule.tag);
+        }
+    }
+
+    for (room, tags) in roooms.rooms.iter_mut().zip(tags) {
+        room.tags = tags;
+    }
+}
+
+fn matches_rule(
+    rule: &TagRule,
+    tags: &[RoomTag],
+    area: usize,
+    depth: f32,
+    degree: usize,
+    distance: Option<usize>,
+) -> bool {
+    let within = |v, min: Option<_>, max: Option<_>| min.is_none_or(|m| v >= m) && max.is_none_or(|m| v <= m);
+    let distance_ok = match distance {
+        Some(d) => within(d, rule.min_distance, rule.max_distance),
+        None => rule.min_distance.is_none() && rule.max_distance.is_none(),
+    };
+
+    !tags.contains(&rule.tag)
+        && rule.require.iter().all(|t| tags.contains(t))
+        && !rule.exclude.iter().any(|t| tags.contains(t))
+        && within(area, rule.min_area, rule.max_area)
+        && depth >= rule.min_depth.unwrap_or(f32::MIN)
+        && depth <= rule.max_depth.unwrap_or(f32::MAX)
+        && within(degree, rule.min_degree, rule.max_degree)
+        && distance_ok
+}
+
+/// edges from the start to every room, `None` when unreachable
+fn graph_distances(adjacency: &[Vec<usize>], start: usize) -> Vec<Option<usize>> {
+    let mut distance = vec![None; adjacency.len()];
+    let mut queue = VecDeque::from([start]);
+    distance[start] = Some(0);
+
+    while let Some(i) = queue.pop_front() {
+        let d = distance[i].expect("queued rooms have a distance");
+        for &j in &adjacency[i] {
+            if distance[j].is_none() {
+                distance[j] = Some(d + 1);
+                queue.push_back(j);
+            }
+        }
+    }
+
+    distance
+}
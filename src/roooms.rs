@@ -1,8 +1,14 @@
 use crate::{
+    portals::{find_room_portals, Portal},
+    prefab::PrefabPlacement,
     room::Room,
+    room_ids::room_uid,
+    room_index::RoomIndex,
+    room_metrics::RoomMetrics,
+    room_split::{split_room, watershed},
     tile_map::{Tile, TileMap},
-    triangulation::{delaunate_rooms, mst_indexes_by_index, triangulation_to_coords},
-    types::Coord,
+    triangulation::{delaunate_rooms, mst_indexes_by_index, room_graph, triangulation_to_coords},
+    types::{Adjacency, CenterStrategy, Coord, FMap, RoomGraphOptions, RoomId, RoomSegmentation},
 };
 use anyhow::Result;
 use delaunator::Triangulation;
@@ -14,24 +20,99 @@ pub struct Roooms {
     triangulation: Option<Triangulation>,
     /// the mininum spanning tree represented as index pairs of the rooms
     pub mst: Option<Vec<(usize, usize)>>,
+    /// the rooms joined by tunnels as index pairs, the spanning tree until
+    /// `build_graph` adds loops to it
+    pub graph: Option<Vec<(usize, usize)>>,
+    /// openings between rooms that touch, where one area was split into
+    /// several, followed by the tunnel openings once the tunnels are carved
+    pub portals: Vec<Portal>,
+    /// point and area lookups over the rooms
+    pub index: RoomIndex,
 }
 
 impl Roooms {
-    pub fn new(tiles: &mut TileMap) -> Result<Roooms> {
+    pub fn new(tiles: &mut TileMap, min_room_size: usize) -> Result<Roooms> {
+        Self::with_prefabs(
+            tiles,
+            &[],
+            min_room_size,
+            None,
+            RoomSegmentation::FloodFill,
+            CenterStrategy::default(),
+            Adjacency::default(),
+        )
+    }
+
+    /// Registers the placed prefabs as the first rooms, whatever their size,
+    /// then finds the rooms of the caves. The doors of a prefab are kept as
+    /// its door tiles, so tunnels and entrances attach there.
+    ///
+    /// Cave rooms below `min_room_size` tiles are filled in, rooms above
+    /// `max_room_size` are split at chokepoints into several rooms. With
+    /// watershed segmentation every cave area is first split into chambers.
+    /// Room centers, which the triangulation and spanning tree connect, are
+    /// placed with `center`. Rooms are filled and their edges found with
+    /// `adjacency`.
+    pub fn with_prefabs(
+        tiles: &mut TileMap,
+        prefabs: &[PrefabPlacement],
+        min_room_size: usize,
+        max_room_size: Option<usize>,
+        segmentation: RoomSegmentation,
+        center: CenterStrategy,
+        adjacency: Adjacency,
+    ) -> Result<Roooms> {
         println!(" new rooooms");
 
         let res = tiles.len();
-        let mut room_counter: u16 = 0;
+        let mut room_counter: RoomId = 0;
         let mut mrooms: Vec<Room> = Vec::new();
 
+        for prefab in prefabs {
+            let start = prefab.open[0];
+            if let Some(mut room) = Room::generate_room((start.x, start.y), tiles, room_counter, 1, adjacency) {
+                if !prefab.doors.is_empty() {
+                    room.door_tile_indexes = room
+                        .tiles
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, t)| prefab.doors.contains(t))
+                        .map(|(i, _)| i)
+                        .collect();
+                }
+                room.prefab = Some(prefab.name.clone());
+                mrooms.push(room);
+                room_counter += 1;
+            }
+        }
+
         for x in 0..res {
             for y in 0..res {
                 match tiles[x][y] {
-                    Tile::Room(_) => match Room::generate_room((x, y), tiles, room_counter, 15) {
+                    Tile::Room(_) => match Room::generate_room((x, y), tiles, room_counter, min_room_size, adjacency) {
                         Some(room) => {
                             tracing::debug!("found room: {:?}", room);
-                            mrooms.push(room);
-                            room_counter += 1;
+                            let max = max_room_size.unwrap_or(usize::MAX);
+                            let chambers = match segmentation {
+                                RoomSegmentation::FloodFill => vec![room.tiles.clone()],
+                                RoomSegmentation::Watershed { saddle_ratio } => {
+                                    watershed(&room.tiles, saddle_ratio, min_room_size, adjacency)
+                                }
+                            };
+                            if chambers.len() == 1 && room.tiles.len() <= max {
+                                mrooms.push(room);
+                                room_counter += 1;
+                                continue;
+                            }
+
+                            for chamber in chambers {
+                                for piece in split_room(&chamber, max, min_room_size, adjacency) {
+                                    let piece = Room::new(piece, room_counter, adjacency);
+                                    piece.mark(tiles);
+                                    mrooms.push(piece);
+                                    room_counter += 1;
+                                }
+                            }
                         }
                         None => {
                             tracing::debug!("No valid room found");
@@ -42,6 +123,13 @@ impl Roooms {
                 }
             }
         }
+        if center != CenterStrategy::default() {
+            for room in &mut mrooms {
+                room.recenter(center);
+                room.mark(tiles);
+            }
+        }
+
         let rooms = mrooms;
         let tri = delaunate_rooms(&rooms).ok();
         let mut mst = None;
@@ -49,13 +137,39 @@ impl Roooms {
         if let Some(tr) = &tri {
             mst = Some(mst_indexes_by_index(&tr, &rooms));
         }
+        let portals = find_room_portals(&rooms, res);
+        let index = RoomIndex::new(&rooms, res);
         Ok(Roooms {
             rooms,
             triangulation: tri,
+            graph: mst.clone(),
             mst,
+            portals,
+            index,
         })
     }
 
+    /// Adds loops to the spanning tree, see `triangulation::room_graph`
+    pub fn build_graph(&mut self, options: &RoomGraphOptions) {
+        if let Some(tr) = &self.triangulation {
+            self.graph = Some(room_graph(tr, &self.rooms, options));
+        }
+    }
+
+    /// Gives every room its stable uid, see `room_ids::room_uid`
+    pub fn identify(&mut self, seed: u32, resolution: usize) {
+        for room in &mut self.rooms {
+            room.uid = room_uid(seed, resolution, room);
+        }
+    }
+
+    /// Computes the metrics of every room from the depth and altitude fields
+    pub fn measure(&mut self, depth: &FMap, altitude: &FMap) {
+        for room in &mut self.rooms {
+            room.metrics = Some(RoomMetrics::measure(room, depth, altitude));
+        }
+    }
+
     pub fn get_room_centers(&self) -> Vec<Coord> {
         self.rooms.iter().map(|room| room.center).collect()
     }
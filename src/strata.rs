@@ -0,0 +1,99 @@
+use noise::{NoiseFn, Simplex};
+
+use crate::{
+    tile_map::{Tile, TileMap},
+    types::{Blank, LayerOptions, StrataOptions, UMap8},
+    utils::{ang, circular_coord},
+};
+
+/// material id for tiles outside the planet, or walls that have not been assigned a layer yet
+pub const MATERIAL_NONE: u8 = 0;
+pub const MATERIAL_CRUST: u8 = 1;
+pub const MATERIAL_MANTLE: u8 = 2;
+pub const MATERIAL_OUTER_CORE: u8 = 3;
+pub const MATERIAL_INNER_CORE: u8 = 4;
+pub const MATERIAL_ORE: u8 = 5;
+pub const MATERIAL_CRYSTAL: u8 = 6;
+pub const MATERIAL_ICE: u8 = 7;
+pub const MATERIAL_MAGMA: u8 = 8;
+
+/// Builds a map of material ids from the normalised depth field.
+///
+/// Each layer claims the tiles whose depth, offset by the layer's own noise
+/// sampled around the planet, falls inside its range. Tiles that fall in a gap
+/// between perturbed ranges take the layer with the nearest nominal range.
+/// Tiles that are empty in `planet` are left as `MATERIAL_NONE`.
+pub fn generate_material_map(
+    planet: &UMap8,
+    depth: &[Vec<f32>],
+    options: &StrataOptions,
+) -> UMap8 {
+    let r = planet.len();
+    let mut out = UMap8::blank(r);
+
+    if options.layers.is_empty() {
+        return out;
+    }
+
+    let noises: Vec<Simplex> = (0..options.layers.len())
+        .map(|i| Simplex::new(options.seed + i as u32))
+        .collect();
+
+    let center = (r as u32 / 2, r as u32 / 2);
+
+    for x in 0..r {
+        for y in 0..r {
+            if planet[x][y] == 0 {
+                continue;
+            }
+
+            let (a, b) = circular_coord(ang((x as u32, y as u32), center), 1.);
+            let d = depth[x][y];
+
+            out[x][y] = options
+                .layers
+                .iter()
+                .zip(noises.iter())
+                .find(|(layer, noise)| {
+                    let n = noise.get([
+                        a as f64 * layer.noise_frequency,
+                        b as f64 * layer.noise_frequency,
+                    ]) as f32;
+                    let perturbed = d + n * layer.noise_amplitude;
+                    perturbed >= layer.min_depth && perturbed < layer.max_depth
+                })
+                .map(|(layer, _)| layer.material)
+                .unwrap_or_else(|| nearest_layer(&options.layers, d).material);
+        }
+    }
+
+    out
+}
+
+fn nearest_layer(layers: &[LayerOptions], depth: f32) -> &LayerOptions {
+    let distance = |layer: &LayerOptions| {
+        if depth < layer.min_depth {
+            layer.min_depth - depth
+        } else if depth > layer.max_depth {
+            depth - layer.max_depth
+        } else {
+            0.
+        }
+    };
+
+    layers
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .expect("nearest_layer called with no layers")
+}
+
+/// Writes the material of each tile into the wall tiles of the tile map
+pub fn apply_materials(tile_map: &mut TileMap, materials: &UMap8) {
+    for (x, row) in tile_map.iter_mut().enumerate() {
+        for (y, tile) in row.iter_mut().enumerate() {
+            if let Tile::Wall(_) = tile {
+                *tile = Tile::Wall(materials[x][y]);
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use rayon::prelude::*;
+
+use crate::types::UMap8;
+
+/// Integral image of a map, answering the sum over any rectangle in constant time
+pub struct SummedAreaTable {
+    /// `sums[x + 1][y + 1]` holds the sum of every tile in `0..=x, 0..=y`
+    sums: Vec<Vec<u32>>,
+    width: i32,
+    height: i32,
+}
+
+impl SummedAreaTable {
+    pub fn new(map: &UMap8) -> Self {
+        let width = map.len();
+        let height = if width > 0 { map[0].len() } else { 0 };
+
+        // prefix sums down each column in parallel, then accumulate across columns
+        let mut sums: Vec<Vec<u32>> = std::iter::once(vec![0; height + 1])
+            .chain(map.par_iter().map(|column| {
+                let mut out = Vec::with_capacity(height + 1);
+                out.push(0);
+                let mut running = 0;
+                for &v in column {
+                    running += v as u32;
+                    out.push(running);
+                }
+                out
+            }).collect::<Vec<_>>())
+            .collect();
+
+        for x in 1..=width {
+            let (done, rest) = sums.split_at_mut(x);
+            let previous = &done[x - 1];
+            for (v, p) in rest[0].iter_mut().zip(previous) {
+                *v += *p;
+            }
+        }
+
+        SummedAreaTable {
+            sums,
+            width: width as i32,
+            height: height as i32,
+        }
+    }
+
+    /// sum over the inclusive rectangle, clipped to the map
+    pub fn rect_sum(&self, x0: i32, x1: i32, y0: i32, y1: i32) -> u32 {
+        let x0 = x0.max(0);
+        let y0 = y0.max(0);
+        let x1 = x1.min(self.width - 1);
+        let y1 = y1.min(self.height - 1);
+
+        if x0 > x1 || y0 > y1 {
+            return 0;
+        }
+
+        let (x0, x1, y0, y1) = (x0 as usize, x1 as usize + 1, y0 as usize, y1 as usize + 1);
+        self.sums[x1][y1] + self.sums[x0][y0] - self.sums[x0][y1] - self.sums[x1][y0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn brute_force(map: &UMap8, x0: i32, x1: i32, y0: i32, y1: i32) -> u32 {
+        let mut sum = 0;
+        for (x, column) in map.iter().enumerate() {
+            for (y, &v) in column.iter().enumerate() {
+                let (x, y) = (x as i32, y as i32);
+                if x >= x0 && x <= x1 && y >= y0 && y <= y1 {
+                    sum += v as u32;
+                }
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn rect_sums_match_brute_force_at_clipped_edges() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let map: UMap8 = (0..7)
+            .map(|_| (0..5).map(|_| rng.gen_range(0..2)).collect())
+            .collect();
+        let table = SummedAreaTable::new(&map);
+
+        for x0 in -3..10 {
+            for x1 in -3..10 {
+                for y0 in -3..8 {
+                    for y1 in -3..8 {
+                        assert_eq!(
+                            table.rect_sum(x0, x1, y0, y1),
+                            brute_force(&map, x0, x1, y0, y1),
+                            "rectangle {}..={} x {}..={}",
+                            x0,
+                            x1,
+                            y0,
+                            y1
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_map_sums_to_zero() {
+        let table = SummedAreaTable::new(&vec![]);
+        assert_eq!(table.rect_sum(-1, 1, -1, 1), 0);
+    }
+}
@@ -1,6 +1,6 @@
 
 
-use crate::types::UMap8;
+use crate::{strata::MATERIAL_NONE, types::{RoomId, UMap8}};
 
 pub type TileMap = Vec<Vec<Tile>>;
 pub trait FromUMap<T: PartialEq> {
@@ -21,7 +21,7 @@ impl FromUMap<u8> for TileMap {
         from.iter()
             .map(|row| {
                 row.iter()
-                    .map(|entry| if *entry == 1 { Tile::Wall } else { Tile::Space })
+                    .map(|entry| if *entry == 1 { Tile::Wall(MATERIAL_NONE) } else { Tile::Space })
                     .collect()
             })
             .collect()
@@ -45,7 +45,7 @@ impl FromUMap<u8> for TileMap {
                 }
                 if *tile == 1 {
                     if rooms[x][y] == 0 {
-                        out[x][y] = Tile::Wall;
+                        out[x][y] = Tile::Wall(MATERIAL_NONE);
                     }
                     if rooms[x][y] == 1 {
                         out[x][y] = Tile::Room(Status::Undesignated);
@@ -60,17 +60,18 @@ impl FromUMap<u8> for TileMap {
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Tile {
     Space,
-    Wall,
+    /// solid rock, holding the material id of the layer it belongs to
+    Wall(u8),
     Surface,
     Room(Status),
-    RoomEdge(u16),
-    RoomCenter(u16),
-    Tunnel(u16)
+    RoomEdge(RoomId),
+    RoomCenter(RoomId),
+    Tunnel(RoomId)
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Status{
-    Designated(u16),
+    Designated(RoomId),
     Undesignated,
 }
 
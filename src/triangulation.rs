@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
 use anyhow::{anyhow, Result};
 use delaunator::Triangulation;
 use petgraph::{
@@ -6,7 +8,11 @@ use petgraph::{
     graph::{NodeIndex, UnGraph},
 };
 
-use crate::{bit_map, room::Room, types::Coord};
+use crate::{
+    bit_map,
+    room::Room,
+    types::{Coord, LoopPreference, RoomGraphOptions},
+};
 
 pub struct RoomTriangulation {
     rooms: Vec<Room>,
@@ -133,4 +139,96 @@ pub fn triangulation_to_coords(tr: &Triangulation, rooms: &Vec<Room>) -> Vec<(Co
     }).collect();
     a
     
-}
\ No newline at end of file
+}
+
+/// The spanning tree of the triangulation plus a fraction of the remaining
+/// triangulation edges, as index pairs of the rooms.
+///
+/// With `LongestCycle` the edges are added one at a time, each time taking
+/// the one whose rooms are the most edges apart in the graph built so far.
+/// Ties go to the shorter edge.
+pub fn room_graph(tr: &Triangulation, rooms: &Vec<Room>, options: &RoomGraphOptions) -> Vec<(usize, usize)> {
+    let mut graph = mst_indexes_by_index(tr, rooms);
+
+    let tree: BTreeSet<(usize, usize)> = graph.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+    let candidates: BTreeSet<(usize, usize)> = get_triangle_edge_indeces(tr)
+        .into_iter()
+        .map(|(a, b)| (a.min(b), a.max(b)))
+        .filter(|e| e.0 != e.1 && !tree.contains(e))
+        .collect();
+
+    let length = |&(a, b): &(usize, usize)| {
+        let (a, b) = (rooms[a].center, rooms[b].center);
+        a.x.abs_diff(b.x).pow(2) + a.y.abs_diff(b.y).pow(2)
+    };
+    let mut candidates: Vec<(usize, usize)> = candidates.into_iter().collect();
+    candidates.sort_by_key(length);
+
+    let count = (options.loop_fraction.clamp(0., 1.) * candidates.len() as f32).round() as usize;
+
+    match options.loop_preference {
+        LoopPreference::Shortest => graph.extend(candidates.into_iter().take(count)),
+        LoopPreference::LongestCycle => {
+            let mut adjacency = vec![vec![]; rooms.len()];
+            for &(a, b) in &graph {
+                adjacency[a].push(b);
+                adjacency[b].push(a);
+            }
+
+            // hops between the rooms of every candidate, one search per room
+            let mut starts: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for (slot, &(a, _)) in candidates.iter().enumerate() {
+                starts.entry(a).or_default().push(slot);
+            }
+            let mut spans = vec![usize::MAX; candidates.len()];
+            for (a, slots) in starts {
+                let distance = hops_from(&adjacency, a);
+                for slot in slots {
+                    spans[slot] = distance[candidates[slot].1];
+                }
+            }
+
+            for _ in 0..count {
+                // candidates are sorted shortest first, so the first of the farthest wins
+                let Some((slot, _)) = spans.iter().enumerate().rev().max_by_key(|&(_, h)| h) else {
+                    break;
+                };
+
+                let (a, b) = candidates.remove(slot);
+                spans.remove(slot);
+
+                // a path can only get shorter by crossing the new edge once
+                let (from_a, from_b) = (hops_from(&adjacency, a), hops_from(&adjacency, b));
+                for (&(x, y), span) in candidates.iter().zip(spans.iter_mut()) {
+                    let via_ab = from_a[x].saturating_add(1).saturating_add(from_b[y]);
+                    let via_ba = from_b[x].saturating_add(1).saturating_add(from_a[y]);
+                    *span = (*span).min(via_ab).min(via_ba);
+                }
+
+                adjacency[a].push(b);
+                adjacency[b].push(a);
+                graph.push((a, b));
+            }
+        }
+    }
+
+    graph
+}
+
+/// edges on the shortest path from a room to every other, `usize::MAX` when not connected
+fn hops_from(adjacency: &[Vec<usize>], from: usize) -> Vec<usize> {
+    let mut distance = vec![usize::MAX; adjacency.len()];
+    let mut queue = VecDeque::from([from]);
+    distance[from] = 0;
+
+    while let Some(i) = queue.pop_front() {
+        for &j in &adjacency[i] {
+            if distance[j] == usize::MAX {
+                distance[j] = distance[i] + 1;
+                queue.push_back(j);
+            }
+        }
+    }
+
+    distance
+}
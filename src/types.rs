@@ -1,12 +1,21 @@
-use crate::room::Room;
+use crate::{
+    room::Room,
+    strata::{
+        MATERIAL_CRUST, MATERIAL_CRYSTAL, MATERIAL_ICE, MATERIAL_INNER_CORE, MATERIAL_MAGMA, MATERIAL_MANTLE,
+        MATERIAL_ORE, MATERIAL_OUTER_CORE,
+    },
+    utils::lerp,
+};
 use glam::Vec2;
 use serde::{Deserialize, Serialize};
-use std::ops::Add;
+use std::{ops::Add, path::PathBuf};
 
 pub type PolyLine = Vec<Vec2>;
 pub type PolyLines = Vec<PolyLine>;
 pub type UMap8 = Vec<Vec<u8>>;
 pub type UMap16 = Vec<Vec<u16>>;
+/// index of a room within one generation, see `Room::uid` for a stable id
+pub type RoomId = u32;
 pub type IMap16 = Vec<Vec<i16>>;
 pub type FMap = Vec<Vec<f32>>;
 
@@ -42,6 +51,7 @@ pub struct PlanetMap {
     pub depth: FMap,
     pub edge_distance_field: Option<FMap>,
     pub mask: Option<FMap>,
+    pub materials: UMap8,
 }
 
 impl PlanetMap {
@@ -55,6 +65,7 @@ impl PlanetMap {
             depth: vec![vec![0.; resolution]; resolution],
             edge_distance_field: Some(vec![vec![0.; resolution]; resolution]),
             mask: Some(vec![vec![0.; resolution]; resolution]),
+            materials: vec![vec![0; resolution]; resolution],
         }
     }
 }
@@ -65,11 +76,27 @@ pub struct PlanetOptions {
     pub radius: f32,
     pub resolution: u32,
     pub ca_options: CaOptions,
+    pub strata_options: StrataOptions,
+    pub material_ca_options: MaterialCaOptions,
+    pub warp_options: WarpOptions,
+    pub connectivity: ConnectivityOptions,
+    pub entrances: SurfaceEntranceOptions,
+    pub prefabs: PrefabOptions,
+    pub room_tags: RoomTagOptions,
+    pub room_graph: RoomGraphOptions,
     pub global_noise_options: GlobalNoiseOptions,
     pub noise_mask_options: NoiseMaskOptions,
     pub blur: f32,
     pub min_room_size: usize,
+    /// rooms above this many tiles are split at chokepoints
+    pub max_room_size: Option<usize>,
+    /// how cave areas are divided into rooms
+    pub room_segmentation: RoomSegmentation,
+    pub room_center: CenterStrategy,
+    /// connectivity of room flood fills, edges and reachability
+    pub room_adjacency: Adjacency,
     pub crust_thickness: f32,
+    /// scale and frequency of the legacy warp, used when `warp_options` has no passes
     pub displacement_scale: f64,
     pub displacement_frequency: f64,
     pub rooms: bool,
@@ -88,6 +115,12 @@ pub struct GlobalNoiseOptions {
     pub z: f64,
     pub frequency: f32,
     pub amplitude: f32,
+    /// moves the surface noise through an extra dimension, for animated surfaces
+    #[serde(default)]
+    pub time: f64,
+    /// when above zero, the surface animation repeats every `loop_period` units of time
+    #[serde(default)]
+    pub loop_period: f64,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -116,6 +149,30 @@ pub struct CaOptions {
     pub threshold: u32,
     pub invert: bool,
     pub mask_options: CaMaskOptions,
+    #[serde(default)]
+    pub modulation: CaModulationOptions,
+    #[serde(default)]
+    pub neighbourhood: CaNeighbourhood,
+    #[serde(default)]
+    pub rule: CaRule,
+    /// steps run in order, replacing `iterations`, `search_radius`,
+    /// `threshold`, `neighbourhood` and `rule` when not empty
+    #[serde(default)]
+    pub schedule: Vec<CaStep>,
+    #[serde(default)]
+    pub counting: CaCounting,
+    /// keep a copy of the map after every iteration, see `PlanetData::ca_frames`
+    #[serde(default)]
+    pub record_frames: bool,
+    /// grayscale PNG scaling `init_weight`, mid grey leaves it unchanged and
+    /// white doubles the weight. Black is forced solid after the CA, like the
+    /// red of `constraint_map`, so no cave opens there
+    #[serde(default)]
+    pub density_map: Option<PathBuf>,
+    /// PNG whose red areas are forced solid and green areas forced open,
+    /// applied to the CA output and respected by tunnels
+    #[serde(default)]
+    pub constraint_map: Option<PathBuf>,
 }
 
 impl Default for CaOptions {
@@ -128,16 +185,824 @@ impl Default for CaOptions {
             threshold: 7,
             invert: false,
             mask_options: CaMaskOptions::default(),
+            modulation: CaModulationOptions::default(),
+            neighbourhood: CaNeighbourhood::default(),
+            rule: CaRule::default(),
+            schedule: vec![],
+            counting: CaCounting::default(),
+            record_frames: false,
+            density_map: None,
+            constraint_map: None,
         }
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+impl CaOptions {
+    pub fn preset(preset: CaPreset) -> Self {
+        match preset {
+            CaPreset::Cellular => Self {
+                init_weight: 0.45,
+                iterations: 5,
+                search_radius: 1,
+                neighbourhood: CaNeighbourhood::Moore,
+                rule: CaRule::BirthSurvival {
+                    birth: 5,
+                    survival: 4,
+                },
+                ..Default::default()
+            },
+            CaPreset::Maze => Self {
+                init_weight: 0.1,
+                iterations: 30,
+                search_radius: 1,
+                neighbourhood: CaNeighbourhood::Moore,
+                rule: CaRule::LifeLike("B3/S12345".to_string()),
+                ..Default::default()
+            },
+            CaPreset::Smooth => Self {
+                init_weight: 0.5,
+                iterations: 6,
+                search_radius: 4,
+                neighbourhood: CaNeighbourhood::Circle,
+                rule: CaRule::Weighted {
+                    sigma: 2.,
+                    threshold: 0.5,
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaPreset {
+    /// classic 4-5 rule caves with rough, cellular walls
+    Cellular,
+    /// long winding corridors from the B3/S12345 rule
+    Maze,
+    /// large rounded caverns from a gaussian weighted majority
+    Smooth,
+}
+
+/// A run of CA iterations with its own neighbourhood and rule, such as
+/// a coarse large radius pass followed by small radius smoothing
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaStep {
+    pub iterations: u32,
+    pub search_radius: u32,
+    pub threshold: u32,
+    pub neighbourhood: CaNeighbourhood,
+    pub rule: CaRule,
+    pub mask: Option<CaStepMask>,
+}
+
+/// Limits a step to the tiles where the field lies within `min..=max`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaStepMask {
+    pub field: CaField,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Which tiles around a cell are counted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaNeighbourhood {
+    /// tiles within `search_radius`, including the tile itself
+    #[default]
+    Circle,
+    /// tiles within `search_radius` along both axes
+    Square,
+    /// tiles within a manhattan distance of `search_radius`
+    VonNeumann,
+    /// the 8 surrounding tiles, ignoring `search_radius`
+    Moore,
+}
+
+/// How neighbours are counted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaCounting {
+    /// visits every tile in the neighbourhood, cost grows with the radius squared
+    #[default]
+    Exact,
+    /// uses a summed area table so the cost does not depend on the radius.
+    /// Exact for squares, approximate for large circles. Other neighbourhoods
+    /// and the weighted rule fall back to exact counting
+    SummedArea,
+}
+
+/// How the neighbour count decides the next state of a cell
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CaRule {
+    /// cleared when the count is above `threshold`, set otherwise
+    #[default]
+    Threshold,
+    /// birth and survival counts as a rule string, e.g. `B3/S23`
+    LifeLike(String),
+    /// follows the majority of its neighbours, keeping its state on a tie
+    Majority,
+    /// set when the gaussian weighted share of set neighbours is above `threshold`
+    Weighted { sigma: f32, threshold: f32 },
+    /// set when an empty cell has at least `birth` set neighbours or a set cell at least `survival`
+    BirthSurvival { birth: u32, survival: u32 },
+}
+
+/// Builds the mask that weights the initial CA state, see `ca_mask::build_ca_mask`.
+///
+/// The surface-distance mask is blurred by `blur`, inverted, scaled by `mult`
+/// and lifted by `lift`. It is then shaped by `depth_falloff` and blended with
+/// each of `images`. The result is clamped to `clamp_min..clamp_max`.
+///
+/// Configs saved before `blur` existed used `mult` as the blur sigma and
+/// ignored the other fields. They are read with that meaning, as
+/// `blur = mult, mult = 2, lift = 1` clamped to `0..1`, so they still build
+/// the same mask.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "CaMaskConfig")]
 pub struct CaMaskOptions {
+    /// sigma of the blur applied to the planet before it is inverted
+    pub blur: f32,
     pub mult: f32,
     pub lift: f32,
     pub clamp_max: f32,
     pub clamp_min: f32,
+    /// multiplies the mask by a curve sampled at the depth of each tile
+    pub depth_falloff: Option<Curve>,
+    /// grayscale images resampled to the resolution and blended in order
+    pub images: Vec<MaskImageOptions>,
+}
+
+impl Default for CaMaskOptions {
+    fn default() -> Self {
+        Self {
+            blur: 0.,
+            mult: 2.,
+            lift: 1.,
+            clamp_max: 1.,
+            clamp_min: 0.,
+            depth_falloff: None,
+            images: vec![],
+        }
+    }
+}
+
+/// `CaMaskOptions` as saved, with `blur` missing from older configs
+#[derive(Deserialize)]
+struct CaMaskConfig {
+    blur: Option<f32>,
+    mult: f32,
+    lift: f32,
+    clamp_max: f32,
+    clamp_min: f32,
+    #[serde(default)]
+    depth_falloff: Option<Curve>,
+    #[serde(default)]
+    images: Vec<MaskImageOptions>,
+}
+
+impl From<CaMaskConfig> for CaMaskOptions {
+    fn from(config: CaMaskConfig) -> Self {
+        let Some(blur) = config.blur else {
+            tracing::warn!("mask options without blur, reading mult {} as the blur sigma", config.mult);
+            return Self {
+                blur: config.mult,
+                depth_falloff: config.depth_falloff,
+                images: config.images,
+                ..Self::default()
+            };
+        };
+
+        Self {
+            blur,
+            mult: config.mult,
+            lift: config.lift,
+            clamp_max: config.clamp_max,
+            clamp_min: config.clamp_min,
+            depth_falloff: config.depth_falloff,
+            images: config.images,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaskImageOptions {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub blend: MaskBlend,
+    /// use one minus the image brightness
+    #[serde(default)]
+    pub invert: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum MaskBlend {
+    #[default]
+    Multiply,
+    Add,
+    Min,
+    Max,
+}
+
+impl MaskBlend {
+    pub fn apply(&self, a: f32, b: f32) -> f32 {
+        match self {
+            MaskBlend::Multiply => a * b,
+            MaskBlend::Add => a + b,
+            MaskBlend::Min => a.min(b),
+            MaskBlend::Max => a.max(b),
+        }
+    }
+}
+
+/// Domain warping applied to the planet before caves are generated
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WarpOptions {
+    pub passes: Vec<WarpPassOptions>,
+}
+
+impl WarpOptions {
+    /// two fbm passes over the binary map, as used when no passes are configured
+    pub fn legacy(scale: f64, frequency: f64) -> Self {
+        WarpOptions {
+            passes: (0..2)
+                .map(|seed| WarpPassOptions {
+                    seed,
+                    scale,
+                    frequency,
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WarpPassOptions {
+    pub seed: u32,
+    pub kind: WarpKind,
+    /// maximum offset in pixels, roughly for fbm and exactly for curl
+    pub scale: f64,
+    pub frequency: f64,
+    pub octaves: usize,
+    pub warp_map: bool,
+    pub warp_altitude: bool,
+    pub warp_depth: bool,
+}
+
+impl Default for WarpPassOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            kind: WarpKind::Fbm,
+            scale: 0.,
+            frequency: 0.,
+            octaves: 6,
+            warp_map: true,
+            warp_altitude: false,
+            warp_depth: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WarpKind {
+    /// independent fbm offsets along each axis
+    #[default]
+    Fbm,
+    /// divergence free offsets from the curl of an fbm field, giving swirling shapes
+    Curl,
+}
+
+/// Varies the CA parameters over the depth or altitude field.
+///
+/// Each curve maps the field value to a multiplier on the matching
+/// `CaOptions` value. Unset curves leave the value unchanged.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CaModulationOptions {
+    pub field: CaField,
+    pub threshold: Option<Curve>,
+    pub search_radius: Option<Curve>,
+    pub init_weight: Option<Curve>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaField {
+    #[default]
+    Depth,
+    Altitude,
+}
+
+/// A piecewise linear curve through `(x, y)` control points.
+/// Values before the first point and after the last are held flat.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Curve {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Curve {
+    pub fn new(mut points: Vec<(f32, f32)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Curve { points }
+    }
+
+    /// an empty curve is flat at 1
+    pub fn sample(&self, x: f32) -> f32 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 1.,
+        };
+
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+
+        self.points
+            .windows(2)
+            .find(|w| x >= w[0].0 && x <= w[1].0)
+            .map(|w| {
+                let span = w[1].0 - w[0].0;
+                if span <= 0. {
+                    return w[1].1;
+                }
+                lerp(w[0].1, w[1].1, (x - w[0].0) / span)
+            })
+            .unwrap_or(last.1)
+    }
+
+    pub fn max(&self) -> f32 {
+        self.points
+            .iter()
+            .map(|p| p.1)
+            .fold(None, |acc: Option<f32>, y| Some(acc.map_or(y, |a| a.max(y))))
+            .unwrap_or(1.)
+    }
+}
+
+/// Concentric layers of the planet, listed from the core outwards
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StrataOptions {
+    pub seed: u32,
+    pub layers: Vec<LayerOptions>,
+}
+
+impl Default for StrataOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            layers: vec![
+                LayerOptions {
+                    material: MATERIAL_INNER_CORE,
+                    min_depth: 0.,
+                    max_depth: 0.2,
+                    noise_amplitude: 0.02,
+                    noise_frequency: 2.,
+                },
+                LayerOptions {
+                    material: MATERIAL_OUTER_CORE,
+                    min_depth: 0.2,
+                    max_depth: 0.4,
+                    noise_amplitude: 0.03,
+                    noise_frequency: 3.,
+                },
+                LayerOptions {
+                    material: MATERIAL_MANTLE,
+                    min_depth: 0.4,
+                    max_depth: 0.85,
+                    noise_amplitude: 0.04,
+                    noise_frequency: 4.,
+                },
+                LayerOptions {
+                    material: MATERIAL_CRUST,
+                    min_depth: 0.85,
+                    max_depth: f32::MAX,
+                    noise_amplitude: 0.02,
+                    noise_frequency: 6.,
+                },
+            ],
+        }
+    }
+}
+
+/// Multi-state CA growing ore, crystal, ice and magma bodies through the layers.
+/// The default has no seeds or transitions and leaves the layers untouched.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MaterialCaOptions {
+    pub seed: u64,
+    pub iterations: u32,
+    pub search_radius: u32,
+    pub seeds: Vec<MaterialSeed>,
+    /// checked in order, the first matching transition whose score reaches its threshold wins
+    pub transitions: Vec<MaterialTransition>,
+}
+
+impl MaterialCaOptions {
+    /// ore veins through the mantle, crystals where ore meets magma,
+    /// ice pockets under the crust and magma chambers near the core
+    pub fn standard() -> Self {
+        use MaterialState::{Material, Rock};
+
+        let grow = |m: u8, threshold: f32| MaterialTransition {
+            from: Rock,
+            to: Material(m),
+            weights: vec![(Material(m), 1.)],
+            threshold,
+        };
+        let erode = |m: u8, threshold: f32| MaterialTransition {
+            from: Material(m),
+            to: Rock,
+            weights: vec![(Rock, 1.)],
+            threshold,
+        };
+
+        Self {
+            seed: 0,
+            iterations: 6,
+            search_radius: 2,
+            seeds: vec![
+                MaterialSeed {
+                    material: MATERIAL_MAGMA,
+                    probability: 0.45,
+                    min_depth: 0.,
+                    max_depth: 0.45,
+                },
+                MaterialSeed {
+                    material: MATERIAL_ORE,
+                    probability: 0.35,
+                    min_depth: 0.35,
+                    max_depth: 0.9,
+                },
+                MaterialSeed {
+                    material: MATERIAL_ICE,
+                    probability: 0.4,
+                    min_depth: 0.85,
+                    max_depth: f32::MAX,
+                },
+            ],
+            transitions: vec![
+                MaterialTransition {
+                    from: Material(MATERIAL_ORE),
+                    to: Material(MATERIAL_CRYSTAL),
+                    weights: vec![(Material(MATERIAL_MAGMA), 1.)],
+                    threshold: 0.2,
+                },
+                MaterialTransition {
+                    from: Material(MATERIAL_ICE),
+                    to: Rock,
+                    weights: vec![(Material(MATERIAL_MAGMA), 1.)],
+                    threshold: 0.1,
+                },
+                erode(MATERIAL_ORE, 0.6),
+                erode(MATERIAL_ICE, 0.6),
+                erode(MATERIAL_MAGMA, 0.55),
+                grow(MATERIAL_MAGMA, 0.5),
+                grow(MATERIAL_ORE, 0.55),
+                grow(MATERIAL_ICE, 0.55),
+            ],
+        }
+    }
+}
+
+/// A material scattered at random before the CA runs, within a depth range
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MaterialSeed {
+    pub material: u8,
+    pub probability: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+/// Turns tiles in state `from` into `to` when the mean weight of their
+/// neighbours, looked up by neighbour state, reaches `threshold`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialTransition {
+    pub from: MaterialState,
+    pub to: MaterialState,
+    pub weights: Vec<(MaterialState, f32)>,
+    pub threshold: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaterialState {
+    /// whatever material the layer at that tile is made of
+    Rock,
+    Material(u8),
+}
+
+/// A single layer, with its range given in units of the depth field
+/// (0 at the center, 1 at the surface)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LayerOptions {
+    pub material: u8,
+    pub min_depth: f32,
+    pub max_depth: f32,
+    /// how far the layer boundaries are pushed around by noise, in depth units
+    pub noise_amplitude: f32,
+    pub noise_frequency: f64,
+}
+
+/// Guarantees every room can be reached from an entrance, see `PlanetData::connectivity`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConnectivityOptions {
+    /// no check is made when unset
+    pub entrance: Option<Entrance>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Entrance {
+    /// the surface tile in this direction from the centre, in radians
+    Angle(f32),
+    /// a tile in grid coordinates
+    Point(usize, usize),
+}
+
+/// Result of the connectivity check over the final tile map
+#[derive(Clone, Debug, Default)]
+pub struct ConnectivityReport {
+    pub entrance: Coord,
+    /// ids of the rooms that needed an extra tunnel to be reached
+    pub repaired: Vec<RoomId>,
+    /// ids of the rooms that could not be reached, for example when a
+    /// repair tunnel was blocked by a forced solid region
+    pub unreachable: Vec<RoomId>,
+}
+
+/// Openings carved from the surface down to the caves, see `PlanetData::entrances`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SurfaceEntranceOptions {
+    #[serde(default)]
+    pub seed: u64,
+    /// number of openings at random angles, used when `angles` is empty
+    #[serde(default)]
+    pub count: usize,
+    /// angles of the openings in radians
+    #[serde(default)]
+    pub angles: Vec<f32>,
+    #[serde(default)]
+    pub kind: EntranceKind,
+    /// width of the passage in tiles
+    pub width: usize,
+}
+
+impl Default for SurfaceEntranceOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            count: 0,
+            angles: vec![],
+            kind: EntranceKind::default(),
+            width: 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum EntranceKind {
+    /// straight down to the closest room until it meets a cave
+    #[default]
+    Shaft,
+    /// a passage curving with the surface towards the nearest room it can
+    /// reach while dropping at most `max_grade` tiles per tile travelled around
+    Slope { max_grade: f32 },
+}
+
+/// An opening in the surface, for spawning
+#[derive(Clone, Debug)]
+pub struct CaveEntrance {
+    pub angle: f32,
+    /// the surface tile of the opening
+    pub surface: Coord,
+    /// the opening in normalised world coordinates
+    pub position: Vec2,
+    /// the room the passage leads to, `None` when a shaft met a tunnel first
+    /// or when no room was in reach and nothing was carved
+    pub room: Option<RoomId>,
+}
+
+/// Hand-authored rooms stamped into the caves after the CA
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PrefabOptions {
+    #[serde(default)]
+    pub seed: u64,
+    pub stamps: Vec<PrefabStampOptions>,
+}
+
+/// A stamp file and where to place it.
+///
+/// PNG stamps use black for solid rock, white for open floor, red for doors
+/// and transparency for tiles left to the caves. Text stamps use `#`, `.`,
+/// `D` and space for the same markers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PrefabStampOptions {
+    /// given to `Room::prefab` for every placed copy
+    pub name: String,
+    pub path: PathBuf,
+    /// copies to place, fewer are placed when no valid spot is found
+    pub count: usize,
+    /// every marked tile of the stamp must lie inside this depth range
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+/// Where in a room its center tile is placed. Every strategy picks a tile
+/// of the room, even for rooms wrapping around rock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CenterStrategy {
+    /// the room tile closest to the mean of its tiles
+    #[default]
+    Centroid,
+    /// the tile furthest from any wall
+    InscribedCircle,
+    /// the pole of inaccessibility of the room outline, found with polylabel
+    Polylabel,
+    /// the tile on the room's medial axis closest to its centroid
+    MedialAxis,
+}
+
+/// Which tiles count as touching when filling rooms and finding their edges
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Adjacency {
+    /// tiles sharing a side
+    #[default]
+    Four,
+    /// tiles sharing a side or a corner
+    Eight,
+}
+
+impl Adjacency {
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        match self {
+            Adjacency::Four => &[(0, -1), (0, 1), (-1, 0), (1, 0)],
+            Adjacency::Eight => &[(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)],
+        }
+    }
+}
+
+/// How connected open space is divided into rooms
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum RoomSegmentation {
+    /// every connected area is one room
+    #[default]
+    FloodFill,
+    /// areas are split into chambers wherever the passage between two is
+    /// narrower than `saddle_ratio` times the width of the smaller one
+    Watershed { saddle_ratio: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RoomTag {
+    Start,
+    Boss,
+    Treasure,
+    DeadEnd,
+    Hub,
+    Secret,
+}
+
+/// Which rooms are joined by tunnels, see `triangulation::room_graph`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RoomGraphOptions {
+    /// fraction of the triangulation edges outside the spanning tree that
+    /// are added back, 0 keeps a tree and 1 uses every edge
+    #[serde(default)]
+    pub loop_fraction: f32,
+    #[serde(default)]
+    pub loop_preference: LoopPreference,
+}
+
+/// Which of the triangulation edges are added back first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoopPreference {
+    /// the shortest edges
+    #[default]
+    Shortest,
+    /// the edges between the rooms furthest apart in the graph so far, which
+    /// close the longest cycles
+    LongestCycle,
+}
+
+/// Rules run in order over the rooms to tag them, see `room_tags::tag_rooms`.
+/// The default has no rules, `standard()` is a starting point.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RoomTagOptions {
+    pub rules: Vec<TagRule>,
+}
+
+impl RoomTagOptions {
+    pub fn standard() -> Self {
+        use RoomTag::*;
+
+        RoomTagOptions {
+            rules: vec![
+                TagRule {
+                    quota: Some(1),
+                    max_distance: Some(0),
+                    ..TagRule::new(Start)
+                },
+                TagRule {
+                    max_degree: Some(1),
+                    exclude: vec![Start],
+                    ..TagRule::new(DeadEnd)
+                },
+                TagRule {
+                    min_degree: Some(3),
+                    ..TagRule::new(Hub)
+                },
+                TagRule {
+                    quota: Some(1),
+                    order: TagOrder::Farthest,
+                    exclude: vec![Start],
+                    ..TagRule::new(Boss)
+                },
+                TagRule {
+                    quota: Some(1),
+                    order: TagOrder::Smallest,
+                    require: vec![DeadEnd],
+                    exclude: vec![Boss],
+                    ..TagRule::new(Secret)
+                },
+                TagRule {
+                    quota: Some(2),
+                    order: TagOrder::Deepest,
+                    require: vec![DeadEnd],
+                    exclude: vec![Boss, Secret],
+                    ..TagRule::new(Treasure)
+                },
+            ],
+        }
+    }
+}
+
+/// Tags the rooms matching every condition, up to `quota` of them taken in `order`.
+///
+/// Distance is counted in room graph edges from the room nearest the
+/// entrance, degree is the number of graph edges of the room and depth is
+/// the mean depth of its tiles, 0 at the centre and 1 at the surface.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TagRule {
+    pub tag: RoomTag,
+    #[serde(default)]
+    pub quota: Option<usize>,
+    #[serde(default)]
+    pub order: TagOrder,
+    #[serde(default)]
+    pub min_area: Option<usize>,
+    #[serde(default)]
+    pub max_area: Option<usize>,
+    #[serde(default)]
+    pub min_depth: Option<f32>,
+    #[serde(default)]
+    pub max_depth: Option<f32>,
+    #[serde(default)]
+    pub min_degree: Option<usize>,
+    #[serde(default)]
+    pub max_degree: Option<usize>,
+    #[serde(default)]
+    pub min_distance: Option<usize>,
+    #[serde(default)]
+    pub max_distance: Option<usize>,
+    /// tags a room must already have
+    #[serde(default)]
+    pub require: Vec<RoomTag>,
+    /// tags a room must not already have
+    #[serde(default)]
+    pub exclude: Vec<RoomTag>,
+}
+
+impl TagRule {
+    /// a rule matching every room
+    pub fn new(tag: RoomTag) -> Self {
+        TagRule {
+            tag,
+            quota: None,
+            order: TagOrder::default(),
+            min_area: None,
+            max_area: None,
+            min_depth: None,
+            max_depth: None,
+            min_degree: None,
+            max_degree: None,
+            min_distance: None,
+            max_distance: None,
+            require: vec![],
+            exclude: vec![],
+        }
+    }
+}
+
+/// which matching rooms a quota keeps first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TagOrder {
+    /// in room order
+    #[default]
+    Any,
+    Nearest,
+    Farthest,
+    Smallest,
+    Largest,
+    Shallowest,
+    Deepest,
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
@@ -178,6 +1043,13 @@ impl Coord {
     pub fn min() -> Coord {
         Coord { x: 0, y: 0 }
     }
+
+    /// The coord one step away, or `None` when it falls outside an r by r map
+    pub fn offset(self, step: (i32, i32), r: usize) -> Option<Coord> {
+        let x = self.x.checked_add_signed(step.0 as isize)?;
+        let y = self.y.checked_add_signed(step.1 as isize)?;
+        (x < r && y < r).then_some(Coord { x, y })
+    }
 }
 
 impl Add<(usize, usize)> for Coord {
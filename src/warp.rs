@@ -0,0 +1,148 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Simplex};
+use rayon::prelude::*;
+
+use crate::{
+    map_data::MapData,
+    types::{FMap, UMap8, WarpKind, WarpOptions, WarpPassOptions},
+};
+
+/// step used for the finite differences of the curl warp, in noise space
+const CURL_EPSILON: f64 = 0.01;
+
+/// per tile offsets in pixels, indexed [x][y]
+pub type Displacement = Vec<Vec<(f32, f32)>>;
+
+/// Runs every pass of the warp stage over the layers each pass selects.
+///
+/// When no passes are configured the legacy behaviour is used: two fbm passes
+/// over the binary map driven by `displacement_scale` and `displacement_frequency`,
+/// now with a different seed for each pass.
+pub fn warp_map_data(
+    map_data: &mut MapData,
+    options: &WarpOptions,
+    displacement_scale: f64,
+    displacement_frequency: f64,
+) {
+    let passes = if options.passes.is_empty() {
+        WarpOptions::legacy(displacement_scale, displacement_frequency).passes
+    } else {
+        options.passes.clone()
+    };
+
+    for pass in &passes {
+        let displacement = displacement_field(pass, map_data.raw_map.len());
+
+        if pass.warp_map {
+            map_data.raw_map = warp_umap(&map_data.raw_map, &displacement);
+        }
+        if pass.warp_altitude {
+            map_data.altitude_field = warp_fmap(&map_data.altitude_field, &displacement);
+        }
+        if pass.warp_depth {
+            map_data.depth_field = warp_fmap(&map_data.depth_field, &displacement);
+        }
+    }
+}
+
+/// Computes the offsets of a single pass, in parallel over rows.
+///
+/// Curl offsets are rescaled so the largest one over the map is `scale`
+/// pixels long, like the roughly unit range of fbm noise.
+pub fn displacement_field(pass: &WarpPassOptions, resolution: usize) -> Displacement {
+    let fbm = Fbm::<Simplex>::new(pass.seed).set_octaves(pass.octaves.max(1));
+    let f = pass.frequency;
+
+    let raw: Vec<Vec<(f64, f64)>> = (0..resolution)
+        .into_par_iter()
+        .map(|x| {
+            (0..resolution)
+                .map(|y| {
+                    let p = [x as f64 * f, y as f64 * f];
+                    match pass.kind {
+                        WarpKind::Fbm => (fbm.get(p), fbm.get([p[0], p[1] + 100.])),
+                        WarpKind::Curl => curl(&fbm, p),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let norm = match pass.kind {
+        WarpKind::Fbm => 1.,
+        WarpKind::Curl => raw
+            .iter()
+            .flatten()
+            .map(|(dx, dy)| dx.hypot(*dy))
+            .fold(0., f64::max),
+    };
+    let scale = if norm > 0. { pass.scale / norm } else { 0. };
+
+    raw.into_par_iter()
+        .map(|column| {
+            column
+                .into_iter()
+                .map(|(dx, dy)| ((dx * scale) as f32, (dy * scale) as f32))
+                .collect()
+        })
+        .collect()
+}
+
+/// divergence free offset, the gradient of the noise rotated by 90 degrees
+fn curl(noise: &Fbm<Simplex>, p: [f64; 2]) -> (f64, f64) {
+    let dndx = (noise.get([p[0] + CURL_EPSILON, p[1]]) - noise.get([p[0] - CURL_EPSILON, p[1]]))
+        / (2. * CURL_EPSILON);
+    let dndy = (noise.get([p[0], p[1] + CURL_EPSILON]) - noise.get([p[0], p[1] - CURL_EPSILON]))
+        / (2. * CURL_EPSILON);
+    (dndy, -dndx)
+}
+
+/// Warps a binary map, resampling bilinearly and thresholding at one half
+pub fn warp_umap(map: &UMap8, displacement: &Displacement) -> UMap8 {
+    (0..map.len())
+        .into_par_iter()
+        .map(|x| {
+            (0..map.len())
+                .map(|y| {
+                    let (dx, dy) = displacement[x][y];
+                    let v = sample_bilinear(map.len(), x as f32 + dx, y as f32 + dy, |sx, sy| {
+                        map[sx][sy] as f32
+                    });
+                    (v >= 0.5) as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Warps a continuous field, resampling bilinearly
+pub fn warp_fmap(map: &FMap, displacement: &Displacement) -> FMap {
+    (0..map.len())
+        .into_par_iter()
+        .map(|x| {
+            (0..map.len())
+                .map(|y| {
+                    let (dx, dy) = displacement[x][y];
+                    sample_bilinear(map.len(), x as f32 + dx, y as f32 + dy, |sx, sy| map[sx][sy])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// samples at a fractional position, clamping to the border of the map
+fn sample_bilinear(r: usize, x: f32, y: f32, get: impl Fn(usize, usize) -> f32) -> f32 {
+    let max = (r - 1) as f32;
+    let x = x.clamp(0., max);
+    let y = y.clamp(0., max);
+
+    let x0 = x.floor() as usize;
+    let y0 = y.floor() as usize;
+    let x1 = (x0 + 1).min(r - 1);
+    let y1 = (y0 + 1).min(r - 1);
+    let tx = x - x0 as f32;
+    let ty = y - y0 as f32;
+
+    let top = get(x0, y0) * (1. - tx) + get(x1, y0) * tx;
+    let bottom = get(x0, y1) * (1. - tx) + get(x1, y1) * tx;
+    top * (1. - ty) + bottom * ty
+}